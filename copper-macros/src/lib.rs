@@ -0,0 +1,267 @@
+//! Derives for copper's packets
+//!
+//! `#[derive(Packet)]` takes a struct whose fields are all `DataType`s and
+//! writes the `Packet`, `Encodable` and `Decodable` impls for it, so nobody
+//! has to line up ids and field orders by hand anymore.
+//!
+//! ```ignore
+//! #[derive(Debug, Packet)]
+//! #[packet(id = 0x02, state = Login, direction = ClientBound)]
+//! pub struct LoginSuccess {
+//!     uuid: Uuid,
+//!     username: ProtocolString,
+//!     number_of_properties: VarInt,
+//!     #[packet(length = number_of_properties)]
+//!     properties: Vec<Property>,
+//! }
+//! ```
+//!
+//! Fields are written in the order they're declared. Two attributes cover
+//! the fields that lean on an earlier one:
+//!
+//! - `#[packet(length = field)]` on a `Vec`, whose length is the earlier
+//!   `VarInt` field instead of a prefix of its own
+//! - `#[packet(optional = field)]` on an `Option`, which is only there when
+//!   the earlier `bool` field is `true`
+//!
+//! The generated code refers to `crate::...`, so this only works inside copper.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Ident, LitInt};
+
+#[proc_macro_derive(Packet, attributes(packet))]
+pub fn derive_packet(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// What `#[packet(...)]` on the struct says
+struct PacketAttributes {
+    id: LitInt,
+    state: Ident,
+    direction: Ident,
+}
+
+impl PacketAttributes {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let mut id = None;
+        let mut state = None;
+        let mut direction = None;
+
+        for attribute in input.attrs.iter().filter(|a| a.path().is_ident("packet")) {
+            attribute.parse_nested_meta(|meta| {
+                if meta.path.is_ident("id") {
+                    id = Some(meta.value()?.parse::<LitInt>()?);
+                } else if meta.path.is_ident("state") {
+                    state = Some(meta.value()?.parse::<Ident>()?);
+                } else if meta.path.is_ident("direction") {
+                    let value = meta.value()?.parse::<Ident>()?;
+                    if value != "ClientBound" && value != "ServerBound" {
+                        return Err(syn::Error::new_spanned(
+                            value,
+                            "direction is either `ClientBound` or `ServerBound`",
+                        ));
+                    }
+                    direction = Some(value);
+                } else {
+                    return Err(meta.error("expected `id`, `state` or `direction`"));
+                }
+                Ok(())
+            })?;
+        }
+
+        let missing = |what| {
+            syn::Error::new_spanned(
+                &input.ident,
+                format!("missing `{what}` in `#[packet(id = .., state = .., direction = ..)]`"),
+            )
+        };
+
+        Ok(Self {
+            id: id.ok_or_else(|| missing("id"))?,
+            state: state.ok_or_else(|| missing("state"))?,
+            direction: direction.ok_or_else(|| missing("direction"))?,
+        })
+    }
+}
+
+/// How a single field goes on the wire
+enum Layout {
+    /// Its own `DataType` impl, prefixes and all
+    Plain,
+    /// A `Vec` without a prefix, as long as the named field says
+    Length(Ident),
+    /// An `Option` without a prefix, there when the named field is `true`
+    Optional(Ident),
+}
+
+struct Field {
+    name: Ident,
+    ty: syn::Type,
+    layout: Layout,
+}
+
+impl Field {
+    fn parse(field: &syn::Field, earlier: &[Self]) -> syn::Result<Self> {
+        let Some(name) = field.ident.clone() else {
+            return Err(syn::Error::new_spanned(field, "packet fields need names"));
+        };
+        let mut layout = Layout::Plain;
+
+        for attribute in field.attrs.iter().filter(|a| a.path().is_ident("packet")) {
+            attribute.parse_nested_meta(|meta| {
+                let make: fn(Ident) -> Layout = if meta.path.is_ident("length") {
+                    Layout::Length
+                } else if meta.path.is_ident("optional") {
+                    Layout::Optional
+                } else {
+                    return Err(meta.error("expected `length` or `optional`"));
+                };
+
+                let other = meta.value()?.parse::<Ident>()?;
+                // it has to be read before this one, or there's nothing to go by
+                if !earlier.iter().any(|field| field.name == other) {
+                    return Err(syn::Error::new_spanned(
+                        other,
+                        "has to be one of the fields before this one",
+                    ));
+                }
+                layout = make(other);
+                Ok(())
+            })?;
+        }
+
+        Ok(Self {
+            name,
+            ty: field.ty.clone(),
+            layout,
+        })
+    }
+
+    fn encode(&self) -> TokenStream2 {
+        let name = &self.name;
+
+        match &self.layout {
+            Layout::Plain => quote! {
+                size += crate::data_types::DataType::write_to(&self.#name, writer)?;
+            },
+            Layout::Length(length) => quote! {
+                if usize::try_from(self.#length.0)? != self.#name.len() {
+                    return Err(crate::ProtocolError::Malformed);
+                }
+                for element in &self.#name {
+                    size += crate::data_types::DataType::write_to(element, writer)?;
+                }
+            },
+            Layout::Optional(present) => quote! {
+                if self.#present != self.#name.is_some() {
+                    return Err(crate::ProtocolError::Malformed);
+                }
+                if let Some(value) = &self.#name {
+                    size += crate::data_types::DataType::write_to(value, writer)?;
+                }
+            },
+        }
+    }
+
+    fn decode(&self) -> TokenStream2 {
+        let Self { name, ty, .. } = self;
+
+        match &self.layout {
+            Layout::Plain => quote! {
+                let #name: #ty = crate::data_types::DataType::read_from(reader)?;
+            },
+            Layout::Length(length) => quote! {
+                let #name: #ty = (0..usize::try_from(#length.0)?)
+                    .map(|_| crate::data_types::DataType::read_from(reader))
+                    .collect::<Result<_, _>>()?;
+            },
+            Layout::Optional(present) => quote! {
+                let #name: #ty = if #present {
+                    Some(crate::data_types::DataType::read_from(reader)?)
+                } else {
+                    None
+                };
+            },
+        }
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let PacketAttributes {
+        id,
+        state,
+        direction,
+    } = PacketAttributes::parse(input)?;
+
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            &input.ident,
+            "only structs can be packets",
+        ));
+    };
+
+    let mut fields: Vec<Field> = vec![];
+    match &data.fields {
+        Fields::Named(named) => {
+            for field in &named.named {
+                let parsed = Field::parse(field, &fields)?;
+                fields.push(parsed);
+            }
+        }
+        Fields::Unit => {}
+        Fields::Unnamed(unnamed) => {
+            return Err(syn::Error::new_spanned(unnamed, "packet fields need names"))
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let encode = fields.iter().map(Field::encode);
+    let decode = fields.iter().map(Field::decode);
+    let names = fields.iter().map(|field| &field.name);
+    let construct = if matches!(data.fields, Fields::Unit) {
+        quote! { Self }
+    } else {
+        quote! { Self { #(#names),* } }
+    };
+
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics crate::packet::Packet for #name #type_generics #where_clause {
+            const ID: i32 = #id;
+            const STATE: crate::State = crate::State::#state;
+            const DIRECTION: crate::packet::Direction = crate::packet::Direction::#direction;
+        }
+
+        #[automatically_derived]
+        impl #impl_generics crate::packet::Encodable for #name #type_generics #where_clause {
+            fn write_to<W: std::io::Write>(
+                &self,
+                writer: &mut W,
+            ) -> Result<usize, crate::ProtocolError> {
+                #[allow(unused_mut)]
+                let mut size = crate::data_types::DataType::write_to(
+                    &crate::data_types::VarInt(<Self as crate::packet::Packet>::ID),
+                    writer,
+                )?;
+                #(#encode)*
+                Ok(size)
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics crate::packet::Decodable for #name #type_generics #where_clause {
+            #[allow(unused_variables)]
+            fn read_from<R: std::io::Read>(reader: &mut R) -> Result<Self, crate::ProtocolError> {
+                #(#decode)*
+                Ok(#construct)
+            }
+        }
+    })
+}
@@ -0,0 +1,255 @@
+//! Checking with a session server that a player really is who they say they are
+//!
+//! After encryption is set up, the client tells the session server that it's
+//! joining a server identified by a hash of the shared secret and our public
+//! key. We then ask the same session server whether that happened, and if it
+//! did we get back the real profile of the player.
+use async_trait::async_trait;
+use md5::{Digest, Md5};
+use serde::Deserialize;
+use tracing::{debug, trace};
+use uuid::Uuid;
+
+use crate::{login::Property, ProtocolError};
+
+/// Where vanilla servers go to verify players
+pub const MOJANG_SESSION_SERVER: &str = "https://sessionserver.mojang.com";
+
+/// The identity of a player, as handed out by a session server
+#[derive(Debug, Clone, Deserialize)]
+pub struct GameProfile {
+    #[serde(rename = "id")]
+    pub uuid: Uuid,
+    pub name: String,
+    #[serde(default)]
+    pub properties: Vec<ProfileProperty>,
+}
+
+impl GameProfile {
+    /// The profile an offline-mode server gives a player, which only depends on their name
+    pub fn offline(name: String) -> Self {
+        Self {
+            uuid: offline_uuid(&name),
+            name,
+            properties: vec![],
+        }
+    }
+}
+
+/// Vanilla's `UUID.nameUUIDFromBytes("OfflinePlayer:" + name)`
+///
+/// That's a version 3 UUID, just without a namespace in front of the name.
+/// Keeping it the same means player data, bans and whitelists carry over.
+pub fn offline_uuid(name: &str) -> Uuid {
+    let digest = Md5::new()
+        .chain_update("OfflinePlayer:")
+        .chain_update(name)
+        .finalize();
+
+    uuid::Builder::from_md5_bytes(digest.into()).into_uuid()
+}
+
+/// Extra data attached to a profile, most importantly the `textures` for skins and capes
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProfileProperty {
+    pub name: String,
+    pub value: String,
+    pub signature: Option<String>,
+}
+
+impl From<Property> for ProfileProperty {
+    fn from(value: Property) -> Self {
+        Self {
+            name: value.name.string,
+            value: value.value.string,
+            signature: value.signature.map(|signature| signature.string),
+        }
+    }
+}
+
+#[async_trait]
+pub trait Authenticator: Send + Sync + std::fmt::Debug {
+    /// Ask whether `username` has announced joining the server identified by `server_hash`
+    ///
+    /// `None` means the session server doesn't know about it, in which case the
+    /// player hasn't proven who they are.
+    async fn has_joined(
+        &self,
+        username: &str,
+        server_hash: &str,
+    ) -> Result<Option<GameProfile>, ProtocolError>;
+}
+
+/// An [`Authenticator`] talking to a Yggdrasil style session server over HTTP
+#[derive(Debug)]
+pub struct SessionServer {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl SessionServer {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Authenticator for SessionServer {
+    async fn has_joined(
+        &self,
+        username: &str,
+        server_hash: &str,
+    ) -> Result<Option<GameProfile>, ProtocolError> {
+        let url = format!("{}/session/minecraft/hasJoined", self.base_url);
+        debug!("Asking {url} about {username:?}");
+
+        let response = self
+            .client
+            .get(url)
+            .query(&[("username", username), ("serverId", server_hash)])
+            .send()
+            .await?
+            .error_for_status()?;
+        trace!("Session server response: {response:?}");
+
+        // anything but a profile (usually 204 No Content) means they never joined
+        if response.status() != reqwest::StatusCode::OK {
+            return Ok(None);
+        }
+
+        Ok(Some(response.json::<GameProfile>().await?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    use super::*;
+
+    /// What Mojang sends back for Notch, minus most of the textures
+    const NOTCH: &str = r#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch","properties":[{"name":"textures","value":"ewogICJ0aW1lc3RhbXAiIDogMA==","signature":"c2lnbmF0dXJl"}]}"#;
+
+    /// A session server that answers one request with `response`, giving back the request line
+    async fn mock(response: Option<String>) -> (SessionServer, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut request = vec![];
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(stream.read_u8().await.unwrap());
+            }
+            let request = String::from_utf8(request).unwrap();
+
+            match response {
+                Some(response) => stream.write_all(response.as_bytes()).await.unwrap(),
+                // never answering, but keeping the connection open
+                None => tokio::time::sleep(Duration::from_mins(1)).await,
+            }
+
+            request.lines().next().unwrap_or_default().to_owned()
+        });
+
+        (SessionServer::new(&url), handle)
+    }
+
+    fn response(status: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[tokio::test]
+    async fn joined() {
+        let (session_server, handle) = mock(Some(response("200 OK", NOTCH))).await;
+
+        let profile = session_server
+            .has_joined("Notch", "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(profile.name, "Notch");
+        assert_eq!(
+            profile.uuid,
+            Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap()
+        );
+        assert_eq!(profile.properties.len(), 1);
+        assert_eq!(profile.properties[0].name, "textures");
+        assert_eq!(
+            profile.properties[0].signature.as_deref(),
+            Some("c2lnbmF0dXJl")
+        );
+
+        // the trailing slash of the base URL doesn't end up doubled
+        assert_eq!(
+            handle.await.unwrap(),
+            "GET /session/minecraft/hasJoined?username=Notch&serverId=-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1 HTTP/1.1"
+        );
+    }
+
+    #[tokio::test]
+    async fn not_joined() {
+        let (session_server, _) = mock(Some(response("204 No Content", ""))).await;
+
+        assert!(session_server
+            .has_joined("Notch", "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn unavailable() {
+        let (session_server, _) = mock(Some(response("503 Service Unavailable", ""))).await;
+
+        assert!(matches!(
+            session_server.has_joined("Notch", "").await,
+            Err(ProtocolError::SessionServer(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        let (session_server, handle) = mock(None).await;
+
+        // the same way the login gives up on it
+        let has_joined = session_server.has_joined("Notch", "");
+        assert!(tokio::time::timeout(Duration::from_millis(200), has_joined)
+            .await
+            .is_err());
+        handle.abort();
+    }
+
+    #[test]
+    fn offline_uuids() {
+        // what a vanilla server in offline mode gives them
+        assert_eq!(
+            offline_uuid("Notch"),
+            Uuid::parse_str("b50ad385-829d-3141-a216-7e7d7539ba7f").unwrap()
+        );
+        assert_eq!(
+            offline_uuid("jeb_"),
+            Uuid::parse_str("a762f560-4fce-3236-812a-b80efff0b62b").unwrap()
+        );
+
+        let uuid = offline_uuid("Notch");
+        assert_eq!(uuid.get_version(), Some(uuid::Version::Md5));
+        assert_eq!(uuid.get_variant(), uuid::Variant::RFC4122);
+
+        // names are case sensitive, like in vanilla
+        assert_ne!(offline_uuid("notch"), uuid);
+    }
+}
@@ -0,0 +1,709 @@
+//! Chat components, the JSON text format used by the MOTD, disconnect reasons and chat
+//!
+//! A component has some content (plain text, a translation key, ...), a style,
+//! and children in `extra` that inherit the style unless they override it.
+//! Vanilla also accepts a bare string or an array in place of a component,
+//! so those are understood when reading, but never written.
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use uuid::Uuid;
+
+/// The character that starts a formatting code in legacy text
+pub const LEGACY_MARKER: char = '\u{a7}';
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "ComponentRepr")]
+pub struct TextComponent {
+    #[serde(flatten)]
+    pub content: Content,
+    #[serde(flatten)]
+    pub style: Style,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra: Vec<Self>,
+}
+
+/// What a component shows, before its children
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Text {
+        text: String,
+    },
+    /// A key from the client's language file, with `with` filling in the `%s`
+    Translate {
+        translate: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        with: Vec<TextComponent>,
+    },
+    Score {
+        score: Score,
+    },
+    /// An entity selector like `@p`, resolved into names by the server
+    Selector {
+        selector: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        separator: Option<Box<TextComponent>>,
+    },
+    /// Whatever key the client has bound to something, like `key.jump`
+    Keybind {
+        keybind: String,
+    },
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Self::Text {
+            text: String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Score {
+    /// A player name or a selector
+    pub name: String,
+    pub objective: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// Formatting, where `None` means the same as the parent
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Style {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bold: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub italic: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub underlined: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strikethrough: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub obfuscated: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub font: Option<String>,
+    /// Put into the chat box when the component is shift-clicked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insertion: Option<String>,
+    #[serde(
+        rename = "clickEvent",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub click_event: Option<ClickEvent>,
+    #[serde(
+        rename = "hoverEvent",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub hover_event: Option<HoverEvent>,
+}
+
+impl Style {
+    /// Fill in everything left as `None` from `parent`
+    fn inherit(&self, parent: &Self) -> Self {
+        Self {
+            color: self.color.or(parent.color),
+            bold: self.bold.or(parent.bold),
+            italic: self.italic.or(parent.italic),
+            underlined: self.underlined.or(parent.underlined),
+            strikethrough: self.strikethrough.or(parent.strikethrough),
+            obfuscated: self.obfuscated.or(parent.obfuscated),
+            font: self.font.clone().or_else(|| parent.font.clone()),
+            insertion: self.insertion.clone().or_else(|| parent.insertion.clone()),
+            click_event: self
+                .click_event
+                .clone()
+                .or_else(|| parent.click_event.clone()),
+            hover_event: self
+                .hover_event
+                .clone()
+                .or_else(|| parent.hover_event.clone()),
+        }
+    }
+
+    /// The formatting codes that get as close to this style as legacy text can
+    fn legacy_codes(&self) -> String {
+        let mut codes = String::new();
+
+        if let Some(code) = self.color.and_then(Color::legacy_code) {
+            codes.push(LEGACY_MARKER);
+            codes.push(code);
+        }
+
+        let formats = [
+            (self.obfuscated, 'k'),
+            (self.bold, 'l'),
+            (self.strikethrough, 'm'),
+            (self.underlined, 'n'),
+            (self.italic, 'o'),
+        ];
+        for (_, code) in formats.iter().filter(|(set, _)| *set == Some(true)) {
+            codes.push(LEGACY_MARKER);
+            codes.push(*code);
+        }
+
+        codes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "value", rename_all = "snake_case")]
+pub enum ClickEvent {
+    OpenUrl(String),
+    RunCommand(String),
+    SuggestCommand(String),
+    /// Only does anything in books, the page number is still sent as a string
+    ChangePage(String),
+    CopyToClipboard(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", content = "contents", rename_all = "snake_case")]
+// the names are what goes in `action`
+#[allow(clippy::enum_variant_names)]
+pub enum HoverEvent {
+    ShowText(Box<TextComponent>),
+    ShowItem {
+        id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        count: Option<i32>,
+        /// The item's NBT, as SNBT
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tag: Option<String>,
+    },
+    ShowEntity {
+        #[serde(rename = "type")]
+        kind: String,
+        id: Uuid,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<Box<TextComponent>>,
+    },
+}
+
+/// One of the 16 named colours, or any other as RGB
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black,
+    DarkBlue,
+    DarkGreen,
+    DarkAqua,
+    DarkRed,
+    DarkPurple,
+    Gold,
+    Gray,
+    DarkGray,
+    Blue,
+    Green,
+    Aqua,
+    Red,
+    LightPurple,
+    Yellow,
+    White,
+    Rgb(u8, u8, u8),
+}
+
+/// The named colours, with their JSON name and legacy formatting code
+const NAMED_COLORS: [(Color, &str, char); 16] = [
+    (Color::Black, "black", '0'),
+    (Color::DarkBlue, "dark_blue", '1'),
+    (Color::DarkGreen, "dark_green", '2'),
+    (Color::DarkAqua, "dark_aqua", '3'),
+    (Color::DarkRed, "dark_red", '4'),
+    (Color::DarkPurple, "dark_purple", '5'),
+    (Color::Gold, "gold", '6'),
+    (Color::Gray, "gray", '7'),
+    (Color::DarkGray, "dark_gray", '8'),
+    (Color::Blue, "blue", '9'),
+    (Color::Green, "green", 'a'),
+    (Color::Aqua, "aqua", 'b'),
+    (Color::Red, "red", 'c'),
+    (Color::LightPurple, "light_purple", 'd'),
+    (Color::Yellow, "yellow", 'e'),
+    (Color::White, "white", 'f'),
+];
+
+impl Color {
+    pub fn from_legacy_code(code: char) -> Option<Self> {
+        NAMED_COLORS
+            .iter()
+            .find(|(_, _, legacy)| *legacy == code.to_ascii_lowercase())
+            .map(|(color, _, _)| *color)
+    }
+
+    /// RGB colours have no code, legacy text can only do the named ones
+    pub fn legacy_code(self) -> Option<char> {
+        NAMED_COLORS
+            .iter()
+            .find(|(color, _, _)| *color == self)
+            .map(|(_, _, code)| *code)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        if let Some(hex) = name.strip_prefix('#') {
+            let rgb = u32::from_str_radix(hex, 16)
+                .ok()
+                .filter(|_| hex.len() == 6)?;
+            let [_, r, g, b] = rgb.to_be_bytes();
+            return Some(Self::Rgb(r, g, b));
+        }
+
+        NAMED_COLORS
+            .iter()
+            .find(|(_, named, _)| *named == name)
+            .map(|(color, _, _)| *color)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Self::Rgb(r, g, b) = self {
+            return write!(f, "#{r:02x}{g:02x}{b:02x}");
+        }
+
+        let name = NAMED_COLORS
+            .iter()
+            .find(|(color, _, _)| color == self)
+            .map_or("white", |(_, name, _)| name);
+        f.write_str(name)
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        Self::from_name(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown color {name:?}")))
+    }
+}
+
+impl TextComponent {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            content: Content::Text { text: text.into() },
+            ..Self::default()
+        }
+    }
+
+    pub fn translate(key: impl Into<String>, with: Vec<Self>) -> Self {
+        Self {
+            content: Content::Translate {
+                translate: key.into(),
+                with,
+            },
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+
+    /// Parse text using `§` formatting codes, like `server.properties` and old clients do
+    ///
+    /// A colour code resets the formatting before it, the same way it does in
+    /// game, and `§r` resets everything. Unknown codes are dropped.
+    pub fn from_legacy(text: &str) -> Self {
+        let mut parts = vec![];
+        let mut style = Style::default();
+        let mut buffer = String::new();
+
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c != LEGACY_MARKER {
+                buffer.push(c);
+                continue;
+            }
+
+            let Some(code) = chars.next() else {
+                break;
+            };
+
+            if !buffer.is_empty() {
+                parts.push(Self::text(std::mem::take(&mut buffer)).with_style(style.clone()));
+            }
+
+            match code.to_ascii_lowercase() {
+                'k' => style.obfuscated = Some(true),
+                'l' => style.bold = Some(true),
+                'm' => style.strikethrough = Some(true),
+                'n' => style.underlined = Some(true),
+                'o' => style.italic = Some(true),
+                'r' => style = Style::default(),
+                code => {
+                    if let Some(color) = Color::from_legacy_code(code) {
+                        style = Style {
+                            color: Some(color),
+                            ..Style::default()
+                        };
+                    }
+                }
+            }
+        }
+
+        if !buffer.is_empty() {
+            parts.push(Self::text(buffer).with_style(style));
+        }
+
+        // plain text stays a plain `{"text": ...}`
+        match parts.len() {
+            0 => Self::text(""),
+            1 if parts[0].style == Style::default() => parts.remove(0),
+            _ => Self {
+                extra: parts,
+                ..Self::text("")
+            },
+        }
+    }
+
+    /// Everything the component says, without any formatting
+    pub fn to_plain(&self) -> String {
+        let mut plain = String::new();
+        self.walk(&Style::default(), &mut |text, _| plain.push_str(text));
+        plain
+    }
+
+    /// The component as `§` formatted text, for clients from before components existed
+    pub fn to_legacy(&self) -> String {
+        let mut legacy = String::new();
+        let mut current = String::new();
+
+        self.walk(&Style::default(), &mut |text, style| {
+            let codes = style.legacy_codes();
+            if codes != current {
+                // formatting can only be turned off by a reset
+                if !current.is_empty() {
+                    legacy.push(LEGACY_MARKER);
+                    legacy.push('r');
+                }
+                legacy.push_str(&codes);
+                current = codes;
+            }
+            legacy.push_str(text);
+        });
+
+        legacy
+    }
+
+    /// Visit the text of every component in order, along with the style it ends up with
+    fn walk(&self, parent: &Style, visit: &mut impl FnMut(&str, &Style)) {
+        let style = self.style.inherit(parent);
+
+        match &self.content {
+            Content::Text { text } => visit(text, &style),
+            // without the client's language file or the scoreboard, the raw values are the best we have
+            Content::Translate { translate, with } => {
+                visit(translate, &style);
+                for argument in with {
+                    argument.walk(&style, visit);
+                }
+            }
+            Content::Score { score } => {
+                visit(score.value.as_deref().unwrap_or_default(), &style);
+            }
+            Content::Selector { selector, .. } => visit(selector, &style),
+            Content::Keybind { keybind } => visit(keybind, &style),
+        }
+
+        for child in &self.extra {
+            child.walk(&style, visit);
+        }
+    }
+}
+
+impl fmt::Display for TextComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_plain())
+    }
+}
+
+impl From<&str> for TextComponent {
+    fn from(text: &str) -> Self {
+        Self::text(text)
+    }
+}
+
+impl From<String> for TextComponent {
+    fn from(text: String) -> Self {
+        Self::text(text)
+    }
+}
+
+/// Every shape vanilla accepts for a component
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ComponentRepr {
+    Text(String),
+    /// The first element is the parent, the rest become its children
+    List(Vec<TextComponent>),
+    Object(Box<ComponentObject>),
+}
+
+#[derive(Deserialize)]
+struct ComponentObject {
+    #[serde(flatten)]
+    content: Content,
+    #[serde(flatten)]
+    style: Style,
+    #[serde(default)]
+    extra: Vec<TextComponent>,
+}
+
+impl From<ComponentRepr> for TextComponent {
+    fn from(repr: ComponentRepr) -> Self {
+        match repr {
+            ComponentRepr::Text(text) => Self::text(text),
+            ComponentRepr::List(list) => {
+                let mut list = list.into_iter();
+                let mut first = list.next().unwrap_or_default();
+                first.extra.extend(list);
+                first
+            }
+            ComponentRepr::Object(object) => Self {
+                content: object.content,
+                style: object.style,
+                extra: object.extra,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn colored(text: &str, color: Color) -> TextComponent {
+        TextComponent::text(text).with_style(Style {
+            color: Some(color),
+            ..Style::default()
+        })
+    }
+
+    #[test]
+    fn from_legacy_plain() {
+        assert_eq!(
+            TextComponent::from_legacy("A Minecraft Server"),
+            TextComponent::text("A Minecraft Server")
+        );
+        assert_eq!(TextComponent::from_legacy(""), TextComponent::text(""));
+    }
+
+    #[test]
+    fn from_legacy_codes() {
+        let component = TextComponent::from_legacy("\u{a7}cRed \u{a7}lbold\u{a7}9blue");
+
+        assert_eq!(
+            component.extra,
+            [
+                colored("Red ", Color::Red),
+                TextComponent::text("bold").with_style(Style {
+                    color: Some(Color::Red),
+                    bold: Some(true),
+                    ..Style::default()
+                }),
+                // a colour turns the bold back off
+                colored("blue", Color::Blue),
+            ]
+        );
+        assert_eq!(component.to_plain(), "Red boldblue");
+
+        // codes are the same in upper case
+        assert_eq!(
+            TextComponent::from_legacy("\u{a7}CRed").extra,
+            [colored("Red", Color::Red)]
+        );
+    }
+
+    #[test]
+    fn from_legacy_reset() {
+        let component = TextComponent::from_legacy("\u{a7}a\u{a7}ogreen\u{a7}rplain");
+
+        assert_eq!(
+            component.extra,
+            [
+                TextComponent::text("green").with_style(Style {
+                    color: Some(Color::Green),
+                    italic: Some(true),
+                    ..Style::default()
+                }),
+                TextComponent::text("plain"),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_legacy_unknown_codes() {
+        // dropped along with their marker, and so is a marker at the very end
+        assert_eq!(
+            TextComponent::from_legacy("\u{a7}zhello\u{a7}"),
+            TextComponent::text("hello")
+        );
+        assert_eq!(
+            TextComponent::from_legacy("\u{a7}6gold\u{a7}xstill gold").extra,
+            [
+                colored("gold", Color::Gold),
+                colored("still gold", Color::Gold)
+            ]
+        );
+    }
+
+    #[test]
+    fn to_legacy() {
+        let component = TextComponent {
+            extra: vec![
+                colored("Red ", Color::Red),
+                TextComponent::text("bold").with_style(Style {
+                    bold: Some(true),
+                    ..Style::default()
+                }),
+                // legacy text has no RGB, so it's left without a colour
+                colored(" rgb", Color::Rgb(1, 2, 3)),
+            ],
+            ..TextComponent::text("Hi ")
+        };
+
+        assert_eq!(
+            component.to_legacy(),
+            "Hi \u{a7}cRed \u{a7}r\u{a7}lbold\u{a7}r rgb"
+        );
+        assert_eq!(component.to_plain(), "Hi Red bold rgb");
+
+        // adding bold still goes through a reset, but it reads back the same
+        let legacy = "\u{a7}cRed \u{a7}lbold\u{a7}r plain";
+        let component = TextComponent::from_legacy(legacy);
+        assert_eq!(
+            component.to_legacy(),
+            "\u{a7}cRed \u{a7}r\u{a7}c\u{a7}lbold\u{a7}r plain"
+        );
+        assert_eq!(
+            TextComponent::from_legacy(&component.to_legacy()),
+            component
+        );
+    }
+
+    #[test]
+    fn to_plain() {
+        let component = TextComponent {
+            extra: vec![
+                TextComponent::translate("chat.type.text", vec!["Steve".into(), "hi".into()]),
+                TextComponent {
+                    content: Content::Keybind {
+                        keybind: "key.jump".to_owned(),
+                    },
+                    ..TextComponent::default()
+                },
+            ],
+            ..TextComponent::text("> ")
+        };
+
+        assert_eq!(component.to_plain(), "> chat.type.textStevehikey.jump");
+        assert_eq!(component.to_string(), component.to_plain());
+    }
+
+    #[test]
+    fn serialize() {
+        assert_eq!(
+            serde_json::to_value(TextComponent::text("hello")).unwrap(),
+            json!({"text": "hello"})
+        );
+
+        let component = TextComponent {
+            extra: vec![
+                colored("red", Color::Red),
+                colored("pink", Color::Rgb(0xff, 0x88, 0xcc)),
+            ],
+            ..TextComponent::text("")
+        };
+        assert_eq!(
+            serde_json::to_value(&component).unwrap(),
+            json!({
+                "text": "",
+                "extra": [
+                    {"text": "red", "color": "red"},
+                    {"text": "pink", "color": "#ff88cc"},
+                ],
+            })
+        );
+
+        assert_eq!(
+            serde_json::to_value(TextComponent::translate(
+                "multiplayer.disconnect.kicked",
+                vec![]
+            ))
+            .unwrap(),
+            json!({"translate": "multiplayer.disconnect.kicked"})
+        );
+    }
+
+    #[test]
+    fn deserialize() {
+        let from = |value| serde_json::from_value::<TextComponent>(value).unwrap();
+
+        // a bare string is just text
+        assert_eq!(from(json!("hello")), TextComponent::text("hello"));
+
+        // the rest of a list are children of the first
+        assert_eq!(
+            from(json!(["a", {"text": "b", "color": "dark_aqua"}])),
+            TextComponent {
+                extra: vec![colored("b", Color::DarkAqua)],
+                ..TextComponent::text("a")
+            }
+        );
+
+        assert_eq!(
+            from(json!({
+                "text": "",
+                "extra": ["plain", {"text": "hex", "color": "#0A0b0C"}],
+            })),
+            TextComponent {
+                extra: vec![
+                    TextComponent::text("plain"),
+                    colored("hex", Color::Rgb(10, 11, 12))
+                ],
+                ..TextComponent::text("")
+            }
+        );
+
+        let component = from(json!({
+            "translate": "chat.type.text",
+            "with": ["Steve"],
+            "bold": true,
+            "clickEvent": {"action": "run_command", "value": "/help"},
+        }));
+        assert_eq!(
+            serde_json::to_value(&component).unwrap(),
+            json!({
+                "translate": "chat.type.text",
+                "with": [{"text": "Steve"}],
+                "bold": true,
+                "clickEvent": {"action": "run_command", "value": "/help"},
+            })
+        );
+    }
+
+    #[test]
+    fn deserialize_invalid_colors() {
+        for color in ["purple", "#12345", "#1234567", "#gggggg", "12ab34"] {
+            let component = serde_json::from_value::<TextComponent>(json!({
+                "text": "",
+                "color": color,
+            }));
+            assert!(component.is_err(), "{color:?}");
+        }
+    }
+}
@@ -1,30 +1,64 @@
 use std::collections::VecDeque;
-use std::io::ErrorKind;
 use std::net::SocketAddr;
+use std::time::Duration;
 
-use tokio::io::AsyncReadExt;
+use bytes::BytesMut;
+use futures::SinkExt;
+use futures::StreamExt;
 use tokio::io::AsyncWriteExt;
-use tokio::io::BufReader;
 use tokio::net::TcpStream;
+use tokio_util::codec::{Decoder, Framed};
 use tracing::debug;
 use tracing::error;
 use tracing::info;
 use tracing::trace;
-
+use tracing::warn;
+
+use crate::auth::GameProfile;
+use crate::chat::TextComponent;
+use crate::codec::FrameCodec;
+use crate::config::AuthMode;
+use crate::config::PlayerInfoForwarding;
+use crate::crypto;
+use crate::data_types::{Identifier, ProtocolString};
+use crate::forwarding;
 use crate::handshaking;
 use crate::login;
 use crate::packet;
+use crate::play;
+use crate::proxy_protocol;
+use crate::server::ServerHandle;
 use crate::status;
 
+use crate::ProtocolError;
 use crate::State;
 
+/// How many random bytes the client has to send back to us during encryption
+const VERIFY_TOKEN_LENGTH: usize = 4;
+
+/// How long a proxy gets to send the PROXY header
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What vanilla tells players when the session server can't be reached
+const AUTHENTICATION_UNAVAILABLE: &str =
+    "Authentication servers are down. Please try again later, sorry!";
+
 pub struct Client {
+    /// The address of the other end of the socket, used to find this connection in the registry
+    peer: SocketAddr,
+    /// Where the client really is, this gets replaced when a proxy tells us otherwise
     addr: SocketAddr,
-    stream: BufReader<TcpStream>,
+    stream: Framed<TcpStream, FrameCodec>,
     state: State,
-    buffer: VecDeque<u8>,
+    /// Which packets the client speaks, ours until the handshake says otherwise
+    protocol_version: i32,
     connected: bool,
     packet_queue: VecDeque<packet::ClientBound>,
+    /// What BungeeCord forwarded in the handshake, waiting for the login to use it
+    bungeecord_forwarding: Option<forwarding::BungeeCordForwarding>,
+    /// The message id the next login plugin request goes out with
+    next_plugin_message_id: i32,
+    server: ServerHandle,
     disconnect_tx: tokio::sync::mpsc::Sender<SocketAddr>,
 }
 
@@ -32,16 +66,20 @@ impl Client {
     pub fn new(
         stream: TcpStream,
         addr: SocketAddr,
+        server: ServerHandle,
         tx: tokio::sync::mpsc::Sender<SocketAddr>,
     ) -> Self {
         Self {
+            peer: addr,
             addr,
-            stream: BufReader::new(stream),
+            stream: Framed::new(stream, FrameCodec::new()),
             state: State::Handshaking,
-            // vecdeque because the bytes are one time read only, so it's fine if its not contiguous for cache hit
-            buffer: VecDeque::new(),
+            protocol_version: crate::PROTOCOL_VERSION,
             connected: true,
             packet_queue: VecDeque::new(),
+            bungeecord_forwarding: None,
+            next_plugin_message_id: 0,
+            server,
             disconnect_tx: tx,
         }
     }
@@ -51,50 +89,510 @@ impl Client {
     // one anyway
     pub async fn handle(&mut self) {
         trace!("Client Stream: {:?}", self.stream);
+        debug!(
+            "Handling ({}), {} connection(s) open",
+            self.addr,
+            self.server.client_count()
+        );
+
+        if let Err(e) = self.run().await {
+            error!(
+                "Client ({}) in {:?} state errored: {e:?}",
+                self.addr, self.state
+            );
+            self.disconnect(&e).await;
+        }
+
+        // client has disconnected, this has to happen no matter how we got here
+        // otherwise the server keeps thinking they're still around
+        if self.disconnect_tx.send(self.peer).await.is_err() {
+            error!(
+                "Disconnect channel closed before ({}) could be removed",
+                self.addr
+            );
+        }
+    }
+
+    async fn run(&mut self) -> Result<(), ProtocolError> {
+        if self.server.config().proxy_protocol {
+            self.read_proxy_header().await?;
+        }
+
+        if let Some(ping) = self.read_legacy_ping().await? {
+            return self.legacy_ping(ping).await;
+        }
 
         while self.connected {
-            // basically, have the thing be drained
-            // then it should be yielding once it empties out.
-            // at this stage you already be writing a response packet to the client
-            // that way the program wont just keep on yielding the drain
-            // and have nothing else to do
-
-            if let Err(e) = self.drain_stream().await {
-                if e.kind() != ErrorKind::WouldBlock {
-                    error!("Error from draining stream: {e:?}");
-                    panic!("Error from draining stream: {e:?}");
-                }
+            let Some(packet) = self.read_packet().await? else {
+                // stream is closed
+                self.connected = false;
+                break;
+            };
+
+            if let packet::ServerBound::Login(login::ServerBound::LoginStart(login_start)) = packet
+            {
+                self.login(login_start).await?;
+                continue;
             }
 
-            while !self.buffer.is_empty() {
-                let packet = match packet::ServerBound::parse_packet(&mut self.buffer, &self.state)
-                {
-                    Ok(packet) => packet,
-                    Err(e) => {
-                        error!("{:?}", e);
-                        panic!();
-                    }
+            self.create_reply(packet)?;
+
+            while !self.packet_queue.is_empty() {
+                self.write_packet().await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Take where the client really is from the PROXY header in front of everything else
+    async fn read_proxy_header(&mut self) -> Result<(), ProtocolError> {
+        // otherwise anyone could connect directly and pretend to be anywhere
+        if !self
+            .server
+            .config()
+            .proxy_protocol_trusted_sources
+            .contains(&self.peer.ip())
+        {
+            warn!(
+                "({}) isn't a trusted proxy, refusing the connection",
+                self.peer
+            );
+            return Err(ProtocolError::ProxyProtocol);
+        }
+
+        // nothing went through the codec yet, so the header is still at the front of the socket
+        let header = proxy_protocol::read_header(self.stream.get_mut());
+        let source = tokio::time::timeout(PROXY_HEADER_TIMEOUT, header)
+            .await
+            .map_err(|_| ProtocolError::Timeout)??;
+
+        if let Some(source) = source {
+            self.addr = source;
+            self.server.set_address(&self.peer, self.addr);
+            debug!("Client ({}) proxied by ({})", self.addr, self.peer);
+        }
+
+        Ok(())
+    }
+
+    /// Check whether the client is from before 1.7, before anything goes through the codec
+    async fn read_legacy_ping(&mut self) -> Result<Option<handshaking::LegacyPing>, ProtocolError> {
+        let mut read = vec![];
+        let ping = handshaking::LegacyPing::read_from(self.stream.get_mut(), &mut read).await?;
+
+        if ping.is_none() {
+            // a modern handshake, which the codec still needs from its first byte
+            self.stream.read_buffer_mut().extend_from_slice(&read);
+        }
+
+        Ok(ping)
+    }
+
+    /// Answer a pre-netty server list ping, which happens outside of the packet codec entirely
+    async fn legacy_ping(&mut self, ping: handshaking::LegacyPing) -> Result<(), ProtocolError> {
+        info!("Legacy {ping:?} ping from ({})", self.addr);
+
+        if !self.server.allow_status(self.addr.ip()) {
+            debug!("Too many pings from ({}), not answering", self.addr);
+            self.connected = false;
+            return Ok(());
+        }
+
+        let status = self.server.status();
+        let mut response = vec![];
+        handshaking::LegacyKick::new(ping, &status).write_to(&mut response)?;
+        trace!("Legacy ping response: {response:?}");
+
+        self.stream.get_mut().write_all(&response).await?;
+        self.connected = false;
+
+        Ok(())
+    }
+
+    /// Wait for the next whole packet, `None` once the client has closed the stream
+    async fn read_packet(&mut self) -> Result<Option<packet::ServerBound>, ProtocolError> {
+        // the codec only yields once a whole frame has arrived, no matter
+        // how the bytes were split up (or bunched together) on the way
+        let frame = match self.buffered_frame()? {
+            Some(frame) => frame,
+            None => match self.stream.next().await {
+                Some(frame) => frame?,
+                None => return Ok(None),
+            },
+        };
+        trace!("Frame: {frame:?}");
+
+        Ok(Some(self.server.registry().decode(
+            self.protocol_version,
+            self.state,
+            &frame,
+        )?))
+    }
+
+    /// A whole frame that's already in the read buffer, without touching the socket
+    ///
+    /// `Framed` only decodes its buffer after it read something itself, so the
+    /// bytes put there by `read_legacy_ping` would otherwise wait for the client
+    /// to send more, which it won't if it sent everything in one go.
+    fn buffered_frame(&mut self) -> Result<Option<BytesMut>, ProtocolError> {
+        if self.stream.read_buffer().is_empty() {
+            return Ok(None);
+        }
+
+        let mut buffer = std::mem::take(self.stream.read_buffer_mut());
+        let frame = self.stream.codec_mut().decode(&mut buffer);
+        *self.stream.read_buffer_mut() = buffer;
+
+        frame
+    }
+
+    /// Drive the login sequence from `LoginStart` all the way to `LoginSuccess`
+    ///
+    /// Unlike the other states this is a back and forth, so instead of going
+    /// through `create_reply` it reads the packets it expects by itself.
+    async fn login(&mut self, login_start: login::LoginStart) -> Result<(), ProtocolError> {
+        info!(
+            "Client ({}) is logging in as {:?}",
+            self.addr, login_start.name.string
+        );
+
+        let forwarded = match self.server.config().player_info_forwarding.clone() {
+            PlayerInfoForwarding::None => None,
+            PlayerInfoForwarding::BungeeCord => {
+                let Some(forwarded) = self.bungeecord_forwarding.take() else {
+                    warn!(
+                        "Client ({}) connected without BungeeCord forwarding",
+                        self.addr
+                    );
+                    return Err(ProtocolError::Disconnect(
+                        "If you wish to use IP forwarding, please enable it in your BungeeCord config as well!"
+                            .to_owned(),
+                    ));
                 };
 
-                self.create_reply(packet);
+                Some(forwarded.into_player(login_start.name.string.clone()))
+            }
+            PlayerInfoForwarding::Velocity { secret } => {
+                Some(self.velocity_forwarding(&secret).await?)
             }
+        };
 
-            while !self.packet_queue.is_empty() {
-                self.write_packet().await;
+        let profile = if let Some(forwarded) = forwarded {
+            self.addr = SocketAddr::new(forwarded.ip, self.addr.port());
+            self.server.set_address(&self.peer, self.addr);
+            debug!("Client ({}) forwarded by ({})", self.addr, self.peer);
+
+            forwarded.profile
+        } else {
+            self.authenticate_login_start(login_start).await?
+        };
+
+        info!(
+            "Client ({}) logged in as {} ({})",
+            self.addr, profile.name, profile.uuid
+        );
+
+        if let Some(threshold) = self.server.config().network_compression_threshold {
+            self.send_packet(packet::ClientBound::Login(
+                login::ClientBound::SetCompression(login::SetCompression::new(threshold)?),
+            ))
+            .await?;
+            self.stream.codec_mut().enable_compression(threshold);
+            debug!(
+                "Compression enabled for ({}) from {threshold} byte(s)",
+                self.addr
+            );
+        }
+
+        self.server.set_profile(&self.peer, profile.clone());
+
+        let properties = profile
+            .properties
+            .into_iter()
+            .map(login::Property::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let login_success = login::LoginSuccess::new(
+            profile.uuid,
+            ProtocolString::try_from(profile.name)?,
+            properties,
+        )?;
+
+        self.send_packet(packet::ClientBound::Login(
+            login::ClientBound::LoginSuccess(login_success),
+        ))
+        .await?;
+        self.state = State::Play;
+
+        Ok(())
+    }
+
+    /// Turn what the client said in `LoginStart` into a profile, the way `auth_mode` says to
+    async fn authenticate_login_start(
+        &mut self,
+        login_start: login::LoginStart,
+    ) -> Result<GameProfile, ProtocolError> {
+        let profile = match self.server.config().auth_mode {
+            AuthMode::Online => {
+                let shared_secret = self.enable_encryption().await?;
+                self.authenticate(&login_start.name.string, &shared_secret)
+                    .await?
+            }
+            AuthMode::Offline => GameProfile::offline(login_start.name.string),
+            AuthMode::TrustedProxy => {
+                // anyone else could just claim to be whoever they like
+                let trusted = self
+                    .server
+                    .config()
+                    .client_uuid_sources
+                    .contains(&self.peer.ip());
+
+                match login_start.player_uuid {
+                    Some(uuid) if trusted => GameProfile {
+                        uuid,
+                        name: login_start.name.string,
+                        properties: vec![],
+                    },
+                    Some(_) => {
+                        warn!(
+                            "Client ({}) sent a UUID without coming through a trusted proxy ({})",
+                            self.addr, self.peer
+                        );
+                        GameProfile::offline(login_start.name.string)
+                    }
+                    None => {
+                        warn!(
+                            "Client ({}) came through the proxy without a UUID",
+                            self.addr
+                        );
+                        GameProfile::offline(login_start.name.string)
+                    }
+                }
+            }
+        };
+
+        Ok(profile)
+    }
+
+    /// Ask Velocity who is really behind this connection
+    async fn velocity_forwarding(
+        &mut self,
+        secret: &str,
+    ) -> Result<forwarding::ForwardedPlayer, ProtocolError> {
+        let response = self
+            .login_plugin_request(
+                &forwarding::VELOCITY_CHANNEL.parse()?,
+                forwarding::velocity_request(),
+            )
+            .await?;
+
+        let Some(response) = response else {
+            warn!(
+                "Client ({}) connected without going through Velocity",
+                self.addr
+            );
+            return Err(ProtocolError::Disconnect(
+                "This server requires you to connect with Velocity.".to_owned(),
+            ));
+        };
+
+        forwarding::read_velocity_response(secret.as_bytes(), &response)
+    }
+
+    /// Exchange the shared secret and switch the stream over to AES/CFB8
+    ///
+    /// Gives back the shared secret, since it's still needed to authenticate the player.
+    async fn enable_encryption(&mut self) -> Result<Vec<u8>, ProtocolError> {
+        let verify_token: [u8; VERIFY_TOKEN_LENGTH] = rand::random();
+        let request = login::EncryptionRequest::new(
+            self.server.keys().public_key_der().to_vec(),
+            verify_token.to_vec(),
+        )?;
+
+        self.send_packet(packet::ClientBound::Login(
+            login::ClientBound::EncryptionRequest(request),
+        ))
+        .await?;
+
+        let Some(packet::ServerBound::Login(login::ServerBound::EncryptionResponse(response))) =
+            self.read_packet().await?
+        else {
+            return Err(ProtocolError::UnexpectedPacket);
+        };
+
+        let keys = self.server.keys();
+        if keys.decrypt(&response.verify_token)? != verify_token {
+            warn!("Client ({}) sent back the wrong verify token", self.addr);
+            return Err(ProtocolError::Encryption);
+        }
+
+        let shared_secret = keys.decrypt(&response.shared_secret)?;
+        self.stream.codec_mut().enable_encryption(&shared_secret)?;
+        debug!("Encryption enabled for ({})", self.addr);
+
+        Ok(shared_secret)
+    }
+
+    /// Ask the session server for the real profile of whoever is logging in
+    async fn authenticate(
+        &self,
+        username: &str,
+        shared_secret: &[u8],
+    ) -> Result<GameProfile, ProtocolError> {
+        let server_hash =
+            crypto::server_hash("", shared_secret, self.server.keys().public_key_der());
+        trace!("Server hash for {username:?}: {server_hash}");
+
+        let has_joined = self
+            .server
+            .authenticator()
+            .has_joined(username, &server_hash);
+
+        match tokio::time::timeout(self.server.config().authentication_timeout, has_joined).await {
+            Ok(Ok(Some(profile))) => Ok(profile),
+            Ok(Ok(None)) => {
+                warn!("Client ({}) failed to verify as {username:?}", self.addr);
+                Err(ProtocolError::Disconnect(
+                    "Failed to verify username!".to_owned(),
+                ))
+            }
+            Ok(Err(e)) => {
+                error!("Couldn't authenticate ({}): {e:?}", self.addr);
+                Err(ProtocolError::Disconnect(
+                    AUTHENTICATION_UNAVAILABLE.to_owned(),
+                ))
+            }
+            Err(_) => {
+                error!("Session server timed out authenticating ({})", self.addr);
+                Err(ProtocolError::Disconnect(
+                    AUTHENTICATION_UNAVAILABLE.to_owned(),
+                ))
             }
         }
+    }
+
+    /// Send a Login Plugin Request on `channel` and wait for the client to answer it
+    ///
+    /// Gives back `None` if the client doesn't understand the channel, which is
+    /// what vanilla clients say to everything. Only usable while logging in.
+    pub async fn login_plugin_request(
+        &mut self,
+        channel: &Identifier,
+        data: Vec<u8>,
+    ) -> Result<Option<Vec<u8>>, ProtocolError> {
+        if self.state != State::Login {
+            error!("Login plugin request on {channel:?} outside of login");
+            return Err(ProtocolError::Internal);
+        }
+
+        let message_id = self.next_plugin_message_id;
+        self.next_plugin_message_id = message_id.wrapping_add(1);
+
+        self.send_packet(packet::ClientBound::Login(
+            login::ClientBound::LoginPluginRequest(login::PluginRequest::new(
+                message_id,
+                channel.clone(),
+                data,
+            )),
+        ))
+        .await?;
+        trace!("Login plugin request {message_id} sent on {channel:?}");
+
+        let timeout = self.server.config().login_plugin_timeout;
+        let Ok(packet) = tokio::time::timeout(timeout, self.read_packet()).await else {
+            warn!(
+                "Client ({}) didn't answer login plugin request on {channel:?}",
+                self.addr
+            );
+            return Err(ProtocolError::Timeout);
+        };
 
-        // client has disconnected
-        self.disconnect_tx.send(self.addr).await;
+        let Some(packet::ServerBound::Login(login::ServerBound::LoginPluginResponse(response))) =
+            packet?
+        else {
+            return Err(ProtocolError::UnexpectedPacket);
+        };
+
+        // requests go out one at a time, so the answer has to be for this one
+        if response.message_id.0 != message_id {
+            warn!(
+                "Client ({}) answered login plugin request {} instead of {message_id}",
+                self.addr, response.message_id.0
+            );
+            return Err(ProtocolError::UnexpectedPacket);
+        }
+
+        if !response.successful {
+            debug!("Client ({}) doesn't understand {channel:?}", self.addr);
+        }
+
+        Ok(response.data.map(|data| data.0))
+    }
+
+    /// Tell the client why they're being kicked, if the state has a way to do that.
+    ///
+    /// Status and handshaking have no disconnect packet so the connection is just closed.
+    async fn disconnect(&mut self, error: &ProtocolError) {
+        let reason = match error {
+            ProtocolError::Disconnect(reason) => TextComponent::text(reason.clone()),
+            // shown by the client as "Connection Lost" with the exception under it
+            error => TextComponent::translate(
+                "disconnect.genericReason",
+                vec![TextComponent::text(format!("Internal Exception: {error}"))],
+            ),
+        };
+
+        let packet = match self.state {
+            State::Handshaking | State::Status => None,
+            State::Login => login::Disconnect::new(&reason)
+                .map(|packet| packet::ClientBound::Login(login::ClientBound::Disconnect(packet)))
+                .ok(),
+            State::Play => play::Disconnect::new(&reason)
+                .map(|packet| packet::ClientBound::Play(play::ClientBound::Disconnect(packet)))
+                .ok(),
+        };
+
+        if let Some(packet) = packet {
+            self.packet_queue.clear();
+
+            if let Err(e) = self.send_packet(packet).await {
+                warn!("Couldn't send disconnect to ({}): {e:?}", self.addr);
+            }
+        }
+
+        self.connected = false;
     }
 
     /// Create packet(s) and then push it to `self.packet_queue`
-    fn create_reply(&mut self, packet_to_write: packet::ServerBound) {
+    fn create_reply(&mut self, packet_to_write: packet::ServerBound) -> Result<(), ProtocolError> {
         let reply_packet: Option<packet::ClientBound> = match packet_to_write {
             packet::ServerBound::Handshake(req) => {
                 info!("Handshake Packet Incoming: {:?}", req);
-                if let handshaking::ServerBound::Handshake(handshake) = req {
-                    self.state = handshake.get_next_state();
+                let handshaking::ServerBound::Handshake(handshake) = req;
+                self.state = handshake.get_next_state();
+
+                // versions we don't know can still ping, with the packets of our own
+                if self
+                    .server
+                    .registry()
+                    .supports(handshake.protocol_version())
+                {
+                    self.protocol_version = handshake.protocol_version();
+                }
+
+                // checked per connection, since a ping is a status request and a ping request on one
+                if self.state == State::Status && !self.server.allow_status(self.addr.ip()) {
+                    debug!("Too many pings from ({}), closing", self.addr);
+                    self.connected = false;
+                    return Ok(());
+                }
+
+                if self.state == State::Login
+                    && self.server.config().player_info_forwarding
+                        == PlayerInfoForwarding::BungeeCord
+                {
+                    self.bungeecord_forwarding =
+                        forwarding::read_bungeecord_address(handshake.server_address())?;
                 }
 
                 None
@@ -102,121 +600,187 @@ impl Client {
             packet::ServerBound::Status(req) => {
                 info!("Status Packet Incoming: {:?}", req);
                 let reply_packet = packet::ClientBound::create_reply(
-                    &self.state,
                     packet::ServerBound::Status(req),
-                );
-
-                let reply_packet = match reply_packet {
-                    Ok(packet) => packet,
-                    Err(e) => {
-                        error!("Error in status packet reply: {e:?}");
-                        todo!("complete error handling");
-                    }
-                };
+                    &self.server,
+                )?;
 
                 info!("Status reply packet: {reply_packet:?}");
 
                 // check if it's a ping response
                 // client doesn't do anything else after this so it's safe to terminate
-                if let packet::ClientBound::Status(status::ClientBound::PingResponse(_)) =
+                if let Some(packet::ClientBound::Status(status::ClientBound::PingResponse(_))) =
                     reply_packet
                 {
                     self.connected = false;
                 }
 
-                Some(reply_packet)
+                reply_packet
             }
 
             packet::ServerBound::Login(req) => {
-                info!("Login Packet Incoming: {:?}", req);
-                let reply_packet =
-                    packet::ClientBound::create_reply(&self.state, packet::ServerBound::Login(req));
-
-                let reply_packet = match reply_packet {
-                    Ok(packet) => packet,
-                    Err(e) => {
-                        error!("Error in login packet reply: {e:?}");
-                        todo!("complete error handling");
-                    }
-                };
-
-                info!("Login reply packet: {reply_packet:?}");
-
-                // check if it's a login success, in which you'd transition to the next state
-                if let packet::ClientBound::Login(login::ClientBound::LoginSuccess(_)) =
-                    reply_packet
-                {
-                    self.state = State::Play;
-                }
-
-                Some(reply_packet)
+                // everything after `LoginStart` is read by `login` itself
+                warn!("Login Packet out of order: {:?}", req);
+                return Err(ProtocolError::UnexpectedPacket);
             }
             packet::ServerBound::Play(req) => {
                 info!("Play Packet Incoming: {:?}", req);
-                let reply_packet =
-                    packet::ClientBound::create_reply(&self.state, packet::ServerBound::Play(req));
-
-                let reply_packet = match reply_packet {
-                    Ok(packet) => packet,
-                    Err(e) => {
-                        error!("Error in login packet reply: {e:?}");
-                        todo!("complete error handling");
-                    }
-                };
-
-                Some(reply_packet)
+                packet::ClientBound::create_reply(packet::ServerBound::Play(req), &self.server)?
             }
         };
 
         if let Some(reply_packet) = reply_packet {
             self.packet_queue.push_back(reply_packet);
         }
+
+        Ok(())
     }
 
-    async fn write_packet(&mut self) {
+    async fn write_packet(&mut self) -> Result<(), ProtocolError> {
         let reply_packet = self.packet_queue.pop_front();
 
         let Some(reply_packet) = reply_packet else {
-            return;
+            return Ok(());
         };
 
-        let reply_bytes = match reply_packet.encode() {
-            Ok(bytes) => bytes,
-            Err(e) => {
-                error!("Error in packet encoding: {e:?}");
-                todo!("complete error handling");
-            }
-        };
+        self.send_packet(reply_packet).await
+    }
 
-        let bytes_written = match self.stream.write(&reply_bytes).await {
-            Ok(bytes_written) => bytes_written,
-            Err(e) => {
-                error!("Error in try_write: {e:?}");
-                todo!("complete error handling");
-            }
-        };
+    async fn send_packet(
+        &mut self,
+        reply_packet: packet::ClientBound,
+    ) -> Result<(), ProtocolError> {
+        let reply_bytes =
+            self.server
+                .registry()
+                .encode(self.protocol_version, self.state, reply_packet)?;
 
-        debug!("Packet written {bytes_written} byte(s)");
         trace!("Packet bytes: {reply_bytes:?}");
+        let bytes_written = reply_bytes.len();
+
+        self.stream.send(reply_bytes).await?;
+
+        debug!("Packet written {bytes_written} byte(s)");
+
+        Ok(())
     }
+}
 
-    /// Drain the whole stream and move it to the client's internal buffer
-    async fn drain_stream(&mut self) -> Result<(), std::io::Error> {
-        trace!("draining stream");
-        let mut buffer: Vec<u8> = vec![0; 128];
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::{
+        io::AsyncReadExt,
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+    use crate::{config::ServerConfig, server::Server};
+
+    /// How long a test waits on the server before deciding it isn't going to answer
+    const ANSWER_TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// A connection to a `Client` handling the other end, like `main` would set it up
+    async fn connect(config: ServerConfig) -> TcpStream {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (accepted, addr) = listener.accept().await.unwrap();
+
+        let server = Arc::new(Server::new(config).unwrap());
+        server.register(addr);
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        tokio::spawn(async move {
+            Client::new(accepted, addr, server, tx).handle().await;
+            // keeps the receiver around until the client is done with it
+            drop(rx);
+        });
+
+        stream
+    }
 
-        match self.stream.read(&mut buffer).await {
-            Ok(bytes_read) => {
-                trace!("Bytes read: {:?}", bytes_read);
-                if bytes_read == 0 {
-                    self.connected = false; // Stream is closed
-                }
+    /// A handshake asking for `next_state`, framed and all
+    fn handshake(next_state: u8) -> Vec<u8> {
+        let mut frame = vec![0x10, 0x00, 0xFB, 0x05, 0x09];
+        frame.extend_from_slice(b"localhost");
+        frame.extend_from_slice(&[0x63, 0xDD, next_state]);
+        frame
+    }
 
-                self.buffer.extend(&buffer[..bytes_read]);
+    /// The id of the next packet the server sends, `None` if it closes the connection instead
+    async fn next_packet_id(stream: &mut TcpStream) -> Option<u8> {
+        let read = async {
+            let mut length = 0;
+            for position in 0..3 {
+                let byte = stream.read_u8().await.ok()?;
+                length |= usize::from(byte & 0x7F) << (7 * position);
+                if byte & 0x80 == 0 {
+                    break;
+                }
             }
-            Err(err) => return Err(err), // Forward other errors
-        }
 
-        Ok(())
+            let mut frame = vec![0; length];
+            stream.read_exact(&mut frame).await.ok()?;
+            frame.first().copied()
+        };
+
+        tokio::time::timeout(ANSWER_TIMEOUT, read)
+            .await
+            .expect("the server never answered")
+    }
+
+    #[tokio::test]
+    async fn handshake_and_request_in_one_write() {
+        let mut stream = connect(ServerConfig::default()).await;
+
+        let mut bytes = handshake(1);
+        // Status Request
+        bytes.extend_from_slice(&[0x01, 0x00]);
+        stream.write_all(&bytes).await.unwrap();
+
+        assert_eq!(next_packet_id(&mut stream).await, Some(0x00));
+    }
+
+    #[tokio::test]
+    async fn handshake_and_request_apart() {
+        let mut stream = connect(ServerConfig::default()).await;
+
+        stream.write_all(&handshake(1)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        stream.write_all(&[0x01, 0x00]).await.unwrap();
+
+        assert_eq!(next_packet_id(&mut stream).await, Some(0x00));
+    }
+
+    #[tokio::test]
+    async fn play_packets_dont_kick() {
+        let config = ServerConfig {
+            auth_mode: AuthMode::Offline,
+            network_compression_threshold: None,
+            ..ServerConfig::default()
+        };
+        let mut stream = connect(config).await;
+
+        let mut bytes = handshake(2);
+        // Login Start for Steve, without a UUID
+        bytes.extend_from_slice(&[0x08, 0x00, 0x05]);
+        bytes.extend_from_slice(b"Steve");
+        bytes.push(0x00);
+        stream.write_all(&bytes).await.unwrap();
+
+        // Login Success
+        assert_eq!(next_packet_id(&mut stream).await, Some(0x02));
+
+        // Client Information, which every client sends right away, then Keep Alive
+        let mut bytes = vec![0x0E, 0x08, 0x05];
+        bytes.extend_from_slice(b"en_us");
+        bytes.extend_from_slice(&[0x0C, 0x00, 0x01, 0x7F, 0x01, 0x00, 0x01]);
+        bytes.extend_from_slice(&[0x09, 0x12, 0, 0, 0, 0, 0, 0, 0, 0x2A]);
+        stream.write_all(&bytes).await.unwrap();
+
+        // no Disconnect, and the connection stays open
+        let read = tokio::time::timeout(Duration::from_millis(300), stream.read_u8()).await;
+        assert!(read.is_err(), "the server answered with {read:?}");
     }
 }
@@ -0,0 +1,391 @@
+//! Framing for the packet stream
+//!
+//! Every packet on the wire is `length | id | data`, where `length` is a
+//! `VarInt` counting the bytes that follow it. TCP doesn't care about those
+//! boundaries, so a single read can hold half a packet or several of them.
+//! [`FrameCodec`] sits between the socket and the per-state decoders and only
+//! ever hands out complete `id | data` frames.
+//!
+//! Once encryption is turned on it also encrypts everything going out and
+//! decrypts everything coming in, since the cipher covers the whole stream
+//! including the length prefixes.
+//!
+//! After Set Compression a frame turns into `length | data length | body`,
+//! where a `data length` of 0 means the body is a plain `id | data`, and
+//! anything else is the size of the `id | data` that the zlib body inflates to.
+use std::io::{Read, Write};
+
+use bytes::{Buf, BufMut, BytesMut};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use tokio_util::codec::{Decoder, Encoder};
+use tracing::{trace, warn};
+
+use crate::{
+    crypto::StreamCipher,
+    data_types::{DataType, VarInt},
+    ProtocolError,
+};
+
+/// The biggest frame the protocol allows, which is the largest number that
+/// fits in a 3 byte `VarInt` (just shy of 2 MiB)
+pub const MAX_FRAME_LENGTH: usize = (1 << 21) - 1;
+
+/// A length prefix is never longer than this
+const MAX_LENGTH_PREFIX_SIZE: usize = 3;
+
+/// The biggest a compressed packet is allowed to claim to inflate to, same as vanilla (8 MiB)
+pub const MAX_UNCOMPRESSED_LENGTH: usize = 1 << 23;
+
+#[derive(Debug, Default)]
+pub struct FrameCodec {
+    cipher: Option<StreamCipher>,
+    /// How many bytes at the front of the read buffer have already been decrypted
+    decrypted: usize,
+    /// Packets at least this big get compressed, `None` while compression is off
+    compression_threshold: Option<usize>,
+}
+
+impl FrameCodec {
+    pub const fn new() -> Self {
+        Self {
+            cipher: None,
+            decrypted: 0,
+            compression_threshold: None,
+        }
+    }
+
+    /// Switch both directions over to the compressed frame format
+    pub const fn enable_compression(&mut self, threshold: usize) {
+        self.compression_threshold = Some(threshold);
+    }
+
+    /// Encrypt the stream from here on out
+    ///
+    /// Anything still sitting in the read buffer arrived after the packet that
+    /// turned encryption on, so it's all treated as encrypted.
+    pub fn enable_encryption(&mut self, shared_secret: &[u8]) -> Result<(), ProtocolError> {
+        self.cipher = Some(StreamCipher::new(shared_secret)?);
+        self.decrypted = 0;
+
+        Ok(())
+    }
+}
+
+/// Try reading the length prefix at the start of `buffer`
+///
+/// Gives back `None` when the buffer ends in the middle of the prefix,
+/// otherwise the frame length and how many bytes the prefix took up.
+fn peek_length(buffer: &[u8]) -> Result<Option<(usize, usize)>, ProtocolError> {
+    let mut length = 0;
+
+    for (position, byte) in buffer.iter().take(MAX_LENGTH_PREFIX_SIZE).enumerate() {
+        length |= usize::from(byte & 0x7F) << (7 * position);
+
+        if byte & 0x80 == 0 {
+            return Ok(Some((length, position + 1)));
+        }
+    }
+
+    if buffer.len() >= MAX_LENGTH_PREFIX_SIZE {
+        // the continuation bit is still set on the last byte we're allowed to read
+        return Err(ProtocolError::FrameTooLarge(MAX_FRAME_LENGTH + 1));
+    }
+
+    Ok(None)
+}
+
+impl Decoder for FrameCodec {
+    type Item = BytesMut;
+    type Error = ProtocolError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if let Some(cipher) = &mut self.cipher {
+            cipher.decrypt(&mut src[self.decrypted..]);
+            self.decrypted = src.len();
+        }
+
+        let Some((length, prefix_size)) = peek_length(src)? else {
+            return Ok(None);
+        };
+
+        if length > MAX_FRAME_LENGTH {
+            return Err(ProtocolError::FrameTooLarge(length));
+        }
+
+        if length == 0 {
+            // there's always at least a packet id
+            return Err(ProtocolError::Malformed);
+        }
+
+        if src.len() < prefix_size + length {
+            trace!(
+                "Partial frame, have {} of {} byte(s)",
+                src.len() - prefix_size,
+                length
+            );
+            src.reserve(prefix_size + length - src.len());
+            return Ok(None);
+        }
+
+        src.advance(prefix_size);
+        self.decrypted = self.decrypted.saturating_sub(prefix_size + length);
+        let frame = src.split_to(length);
+
+        match self.compression_threshold {
+            Some(threshold) => Ok(Some(decompress(frame, threshold)?)),
+            None => Ok(Some(frame)),
+        }
+    }
+}
+
+/// Turn a `data length | body` frame back into `id | data`
+fn decompress(mut frame: BytesMut, threshold: usize) -> Result<BytesMut, ProtocolError> {
+    let mut reader = frame.as_ref();
+    let data_length = usize::try_from(VarInt::read_from(&mut reader)?.0)?;
+    let body_start = frame.len() - reader.len();
+
+    if data_length == 0 {
+        // below the threshold, so it was sent as is
+        frame.advance(body_start);
+        return Ok(frame);
+    }
+
+    // the size is whatever the client says it is, so it has to be checked
+    // before trusting it with an allocation
+    if data_length < threshold || data_length > MAX_UNCOMPRESSED_LENGTH {
+        warn!("Compressed packet claims a size of {data_length}, threshold is {threshold}");
+        return Err(ProtocolError::Compression);
+    }
+
+    let mut data = Vec::with_capacity(data_length);
+    // read one byte past the declared size, so bodies that inflate to more are caught
+    ZlibDecoder::new(reader)
+        .take(u64::try_from(data_length)? + 1)
+        .read_to_end(&mut data)
+        .map_err(|_| ProtocolError::Compression)?;
+
+    if data.len() != data_length {
+        warn!(
+            "Compressed packet inflated to {} byte(s) instead of {data_length}",
+            data.len()
+        );
+        return Err(ProtocolError::Compression);
+    }
+
+    Ok(BytesMut::from(&data[..]))
+}
+
+/// Turn an `id | data` frame into `data length | body`
+fn compress(item: &[u8], threshold: usize) -> Result<Vec<u8>, ProtocolError> {
+    let mut frame = vec![];
+
+    if item.len() < threshold {
+        VarInt(0).write_to(&mut frame)?;
+        frame.extend_from_slice(item);
+        return Ok(frame);
+    }
+
+    VarInt::try_from(item.len())?.write_to(&mut frame)?;
+    let mut encoder = ZlibEncoder::new(frame, Compression::default());
+    encoder.write_all(item)?;
+
+    Ok(encoder.finish()?)
+}
+
+impl Encoder<Vec<u8>> for FrameCodec {
+    type Error = ProtocolError;
+
+    fn encode(&mut self, item: Vec<u8>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        let item = match self.compression_threshold {
+            Some(threshold) => compress(&item, threshold)?,
+            None => item,
+        };
+
+        if item.len() > MAX_FRAME_LENGTH {
+            return Err(ProtocolError::FrameTooLarge(item.len()));
+        }
+
+        let length = VarInt::try_from(item.len())?;
+        dst.reserve(length.size() + item.len());
+
+        // there might be earlier frames in here that were already encrypted
+        let start = dst.len();
+
+        let mut writer = dst.writer();
+        length.write_to(&mut writer)?;
+        writer.into_inner().extend_from_slice(&item);
+
+        if let Some(cipher) = &mut self.cipher {
+            cipher.encrypt(&mut dst[start..]);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(codec: &mut FrameCodec, item: &[u8]) -> BytesMut {
+        let mut dst = BytesMut::new();
+        codec.encode(item.to_vec(), &mut dst).unwrap();
+        dst
+    }
+
+    #[test]
+    fn split_frames() {
+        let mut codec = FrameCodec::new();
+        let frame = [0x03, 0x00, 0xAA, 0xBB];
+
+        // a byte at a time, which is the worst TCP could do
+        let mut src = BytesMut::new();
+        for byte in &frame[..frame.len() - 1] {
+            src.put_u8(*byte);
+            assert!(codec.decode(&mut src).unwrap().is_none());
+        }
+        src.put_u8(frame[frame.len() - 1]);
+
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap()[..], frame[1..]);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn coalesced_frames() {
+        let mut codec = FrameCodec::new();
+        let mut src = BytesMut::from(&[0x01, 0x00, 0x02, 0x01, 0x2A, 0x02][..]);
+
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap()[..], [0x00]);
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap()[..], [0x01, 0x2A]);
+        // the start of a third one stays in the buffer
+        assert!(codec.decode(&mut src).unwrap().is_none());
+        assert_eq!(src[..], [0x02]);
+    }
+
+    #[test]
+    fn length_prefix_limits() {
+        let mut codec = FrameCodec::new();
+
+        // the biggest 3 byte VarInt is allowed, the rest just hasn't arrived yet
+        let mut src = BytesMut::from(&[0xFF, 0xFF, 0x7F][..]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+
+        // a 4th byte never gets looked at
+        let mut src = BytesMut::from(&[0x80, 0x80, 0x80][..]);
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(ProtocolError::FrameTooLarge(_))
+        ));
+
+        // but a prefix cut off after 2 bytes could still be fine
+        let mut src = BytesMut::from(&[0x80, 0x80][..]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+
+        let mut src = BytesMut::from(&[0x00][..]);
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(ProtocolError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut codec = FrameCodec::new();
+
+        let item = vec![0x42; 300];
+        let mut src = encode(&mut codec, &item);
+        assert_eq!(src[..2], [0xAC, 0x02]);
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap()[..], item[..]);
+
+        let mut dst = BytesMut::new();
+        assert!(matches!(
+            codec.encode(vec![0; MAX_FRAME_LENGTH + 1], &mut dst),
+            Err(ProtocolError::FrameTooLarge(_))
+        ));
+        assert!(dst.is_empty());
+
+        let mut src = encode(&mut codec, &vec![0; MAX_FRAME_LENGTH]);
+        assert_eq!(src[..3], [0xFF, 0xFF, 0x7F]);
+        assert_eq!(
+            codec.decode(&mut src).unwrap().unwrap().len(),
+            MAX_FRAME_LENGTH
+        );
+    }
+
+    /// A compressed frame claiming to inflate to `data_length`, whatever `data` really is
+    fn compressed_frame(data_length: usize, data: &[u8]) -> BytesMut {
+        let mut body = vec![];
+        VarInt::try_from(data_length)
+            .unwrap()
+            .write_to(&mut body)
+            .unwrap();
+        let mut encoder = ZlibEncoder::new(body, Compression::default());
+        encoder.write_all(data).unwrap();
+        let body = encoder.finish().unwrap();
+
+        let mut frame = BytesMut::new();
+        VarInt::try_from(body.len())
+            .unwrap()
+            .write_to(&mut (&mut frame).writer())
+            .unwrap();
+        frame.extend_from_slice(&body);
+        frame
+    }
+
+    #[test]
+    fn compression() {
+        let mut codec = FrameCodec::new();
+        codec.enable_compression(256);
+
+        // under the threshold it's only marked as uncompressed
+        let small = [0x00, 0x01, 0x02];
+        let mut src = encode(&mut codec, &small);
+        assert_eq!(src[..], [0x04, 0x00, 0x00, 0x01, 0x02]);
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap()[..], small);
+
+        let large = vec![0x42; 1000];
+        let mut src = encode(&mut codec, &large);
+        assert!(src.len() < large.len());
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap()[..], large[..]);
+
+        // exactly the threshold is compressed too
+        let mut src = compressed_frame(256, &[0; 256]);
+        assert_eq!(codec.decode(&mut src).unwrap().unwrap().len(), 256);
+    }
+
+    #[test]
+    fn compressed_sizes_are_checked() {
+        let mut codec = FrameCodec::new();
+        codec.enable_compression(256);
+
+        // vanilla never compresses anything this small, so neither may the client
+        let mut src = compressed_frame(255, &[0; 255]);
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(ProtocolError::Compression)
+        ));
+
+        // turned down before anything gets allocated for it
+        let mut src = compressed_frame(MAX_UNCOMPRESSED_LENGTH + 1, &[0; 16]);
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(ProtocolError::Compression)
+        ));
+
+        // more or less than it said
+        for data_length in [999, 1001] {
+            let mut src = compressed_frame(data_length, &[0; 1000]);
+            assert!(matches!(
+                codec.decode(&mut src),
+                Err(ProtocolError::Compression)
+            ));
+        }
+
+        // the biggest size it's allowed to claim
+        let mut src = compressed_frame(MAX_UNCOMPRESSED_LENGTH, &vec![0; MAX_UNCOMPRESSED_LENGTH]);
+        assert_eq!(
+            codec.decode(&mut src).unwrap().unwrap().len(),
+            MAX_UNCOMPRESSED_LENGTH
+        );
+    }
+}
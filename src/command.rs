@@ -0,0 +1,78 @@
+//! Commands run from outside of the game, like over RCON
+//!
+//! Each one gets the server and whatever came after its name, and gives back
+//! its output as text for whoever ran it.
+use crate::server::Server;
+
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    run: fn(&Server, &[&str]) -> String,
+}
+
+const COMMANDS: &[Command] = &[
+    Command {
+        name: "help",
+        usage: "help",
+        description: "Shows every command",
+        run: help,
+    },
+    Command {
+        name: "list",
+        usage: "list [uuids]",
+        description: "Shows who is online",
+        run: list,
+    },
+];
+
+/// Run one line of input, with or without the `/` in front
+pub fn execute(server: &Server, line: &str) -> String {
+    let line = line.trim();
+    let line = line.strip_prefix('/').unwrap_or(line);
+
+    let mut words = line.split_whitespace();
+    let Some(name) = words.next() else {
+        return String::new();
+    };
+    let arguments: Vec<&str> = words.collect();
+
+    COMMANDS
+        .iter()
+        .find(|command| command.name.eq_ignore_ascii_case(name))
+        .map_or_else(
+            || format!("Unknown or incomplete command: {name}, see help"),
+            |command| (command.run)(server, &arguments),
+        )
+}
+
+fn help(_server: &Server, _arguments: &[&str]) -> String {
+    COMMANDS
+        .iter()
+        .map(|command| format!("/{} - {}", command.usage, command.description))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn list(server: &Server, arguments: &[&str]) -> String {
+    let players = server.players();
+    let with_uuids = arguments.first() == Some(&"uuids");
+
+    let names: Vec<String> = players
+        .iter()
+        .map(|player| {
+            if with_uuids {
+                format!("{} ({})", player.name, player.uuid)
+            } else {
+                player.name.clone()
+            }
+        })
+        .collect();
+
+    format!(
+        "There are {} of a max of {} players online: {}",
+        players.len(),
+        server.config().max_players,
+        names.join(", ")
+    )
+}
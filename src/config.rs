@@ -0,0 +1,520 @@
+//! Settings that change how the server behaves
+//!
+//! They're read from a vanilla style `server.properties`, so an existing one
+//! keeps working. Keys vanilla doesn't have are added for what only we do,
+//! and any key missing from the file is written back with its default.
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{
+    auth::MOJANG_SESSION_SERVER, favicon::DEFAULT_FAVICON_PATH, properties::Properties,
+    rate_limit::RateLimit,
+};
+
+/// Where the config is looked for, same as vanilla
+pub const SERVER_PROPERTIES: &str = "server.properties";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Couldn't access {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// A value that doesn't parse, or doesn't make sense with the rest of the config
+    #[error("{key}={value:?} is invalid: {reason}")]
+    Invalid {
+        key: &'static str,
+        value: String,
+        reason: String,
+    },
+}
+
+/// How the identity a client gives in `LoginStart` becomes the profile they play with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMode {
+    /// Players prove who they are through encryption and the session server
+    Online,
+    /// Anyone can be anyone, the UUID is derived from the name like vanilla does
+    Offline,
+    /// A proxy in front of us already checked the player, so the UUID it sends is taken as is,
+    /// but only from one of `client_uuid_sources`
+    TrustedProxy,
+}
+
+/// Where a proxy in front of us puts the real address and profile of a player
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayerInfoForwarding {
+    None,
+    /// BungeeCord's `ip_forward`, which packs everything into the handshake's server address
+    BungeeCord,
+    /// Velocity's modern forwarding, through a login plugin request signed with `secret`
+    Velocity {
+        secret: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Where the game listens, query and RCON use the same IP
+    pub address: SocketAddr,
+    pub auth_mode: AuthMode,
+    /// The proxies whose `LoginStart` UUIDs are believed with [`AuthMode::TrustedProxy`]
+    pub client_uuid_sources: Vec<IpAddr>,
+    /// Forwarded players skip `auth_mode`, the proxy has already dealt with them
+    pub player_info_forwarding: PlayerInfoForwarding,
+    /// Base URL of the session server asked about `hasJoined`
+    pub session_server: String,
+    /// Whether connections start with a PROXY protocol header (v1 or v2)
+    pub proxy_protocol: bool,
+    /// The only peers allowed to send a PROXY header, everyone else gets refused
+    pub proxy_protocol_trusted_sources: Vec<IpAddr>,
+    /// How long to wait on the session server before giving up on a login
+    pub authentication_timeout: Duration,
+    /// How long a client gets to answer a login plugin request
+    pub login_plugin_timeout: Duration,
+    /// Packets at least this many bytes long get compressed, `None` turns compression off
+    pub network_compression_threshold: Option<usize>,
+    /// The description shown under the server's name in the server list, with `§` formatting codes
+    pub motd: String,
+    pub max_players: usize,
+    /// Where the 64x64 PNG shown in the server list is
+    pub favicon_path: PathBuf,
+    /// How often one IP can ask for the status (legacy pings included), `None` for no limit
+    pub status_rate_limit: Option<RateLimit>,
+    /// The UDP port to answer GameSpy4 queries on, `None` to not listen at all
+    pub query_port: Option<u16>,
+    /// The TCP port for remote console connections, `None` to not listen at all
+    pub rcon_port: Option<u16>,
+    /// RCON won't start without one
+    pub rcon_password: String,
+    /// Whether clients are told that chat messages have to be signed
+    pub enforce_secure_profile: bool,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 25565),
+            auth_mode: AuthMode::Online,
+            client_uuid_sources: vec![],
+            player_info_forwarding: PlayerInfoForwarding::None,
+            proxy_protocol: false,
+            proxy_protocol_trusted_sources: vec![],
+            session_server: MOJANG_SESSION_SERVER.to_owned(),
+            authentication_timeout: Duration::from_secs(10),
+            login_plugin_timeout: Duration::from_secs(5),
+            network_compression_threshold: Some(256),
+            motd: "A Minecraft Server".to_owned(),
+            max_players: 20,
+            favicon_path: PathBuf::from(DEFAULT_FAVICON_PATH),
+            enforce_secure_profile: true,
+            query_port: None,
+            rcon_port: None,
+            rcon_password: String::new(),
+            status_rate_limit: Some(RateLimit {
+                burst: 10,
+                refill: Duration::from_secs(1),
+            }),
+        }
+    }
+}
+
+/// Ports vanilla uses when query or RCON get enabled without one
+const DEFAULT_QUERY_PORT: u16 = 25565;
+const DEFAULT_RCON_PORT: u16 = 25575;
+
+impl ServerConfig {
+    /// Read the config at `path`, writing it first if it isn't there
+    ///
+    /// Keys missing from the file get their default and are added to it, so
+    /// every setting can be found in there after the first start.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let io_error = |source| ConfigError::Io {
+            path: path.to_owned(),
+            source,
+        };
+
+        let mut properties = match std::fs::read_to_string(path) {
+            Ok(text) => Properties::parse(&text),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("No {}, creating it", path.display());
+                Properties::default()
+            }
+            Err(e) => return Err(io_error(e)),
+        };
+
+        let defaults = Self::default().to_properties();
+        let mut missing = false;
+        for key in defaults.keys() {
+            if properties.get(key).is_none() {
+                missing = true;
+                properties.set(key, defaults.get(key).unwrap_or_default().to_owned());
+            }
+        }
+
+        for key in properties.keys() {
+            if defaults.get(key).is_none() {
+                warn!("Unknown key {key:?} in {}, ignoring it", path.display());
+            }
+        }
+
+        let config = Self::from_properties(&properties)?;
+
+        if missing {
+            let contents = format!("#Minecraft server properties\n{properties}");
+            std::fs::write(path, contents).map_err(io_error)?;
+        }
+
+        Ok(config)
+    }
+
+    fn from_properties(properties: &Properties) -> Result<Self, ConfigError> {
+        let reader = Reader(properties);
+
+        let ip = match reader.string("server-ip")? {
+            "" => Ipv4Addr::UNSPECIFIED.into(),
+            _ => reader.parse("server-ip")?,
+        };
+
+        let auth_mode = match (
+            reader.parse("online-mode")?,
+            reader.parse("accept-client-uuids")?,
+        ) {
+            (true, _) => AuthMode::Online,
+            (false, false) => AuthMode::Offline,
+            (false, true) => AuthMode::TrustedProxy,
+        };
+
+        let client_uuid_sources = reader.addresses("client-uuid-sources")?;
+        if auth_mode == AuthMode::TrustedProxy && client_uuid_sources.is_empty() {
+            return Err(reader.invalid(
+                "client-uuid-sources",
+                "accept-client-uuids needs the proxies to accept them from",
+            ));
+        }
+
+        let player_info_forwarding = match reader.string("player-info-forwarding")? {
+            "none" => PlayerInfoForwarding::None,
+            "bungeecord" => PlayerInfoForwarding::BungeeCord,
+            "velocity" => {
+                let secret = reader.string("velocity-forwarding-secret")?;
+                if secret.is_empty() {
+                    return Err(reader.invalid(
+                        "velocity-forwarding-secret",
+                        "velocity forwarding needs the secret configured in Velocity",
+                    ));
+                }
+
+                PlayerInfoForwarding::Velocity {
+                    secret: secret.to_owned(),
+                }
+            }
+            _ => {
+                return Err(reader.invalid(
+                    "player-info-forwarding",
+                    "expected none, bungeecord or velocity",
+                ))
+            }
+        };
+
+        let proxy_protocol: bool = reader.parse("proxy-protocol")?;
+        let proxy_protocol_trusted_sources = reader.addresses("proxy-protocol-trusted-sources")?;
+        if proxy_protocol && proxy_protocol_trusted_sources.is_empty() {
+            return Err(reader.invalid(
+                "proxy-protocol-trusted-sources",
+                "proxy-protocol needs the proxies to accept the header from",
+            ));
+        }
+
+        // vanilla turns compression off with anything negative
+        let network_compression_threshold: i32 = reader.parse("network-compression-threshold")?;
+        let network_compression_threshold = usize::try_from(network_compression_threshold).ok();
+
+        let status_rate_limit_burst: u32 = reader.parse("status-rate-limit-burst")?;
+        let status_rate_limit_refill: u64 = reader.parse("status-rate-limit-refill-millis")?;
+        if status_rate_limit_refill == 0 {
+            return Err(reader.invalid("status-rate-limit-refill-millis", "has to be more than 0"));
+        }
+        let status_rate_limit = (status_rate_limit_burst > 0).then(|| RateLimit {
+            burst: status_rate_limit_burst,
+            refill: Duration::from_millis(status_rate_limit_refill),
+        });
+
+        let rcon_password = reader.string("rcon.password")?.to_owned();
+        let rcon_port = if reader.parse("enable-rcon")? {
+            if rcon_password.is_empty() {
+                return Err(reader.invalid("rcon.password", "RCON is enabled without a password"));
+            }
+            Some(reader.parse("rcon.port")?)
+        } else {
+            None
+        };
+
+        let query_port = if reader.parse("enable-query")? {
+            Some(reader.parse("query.port")?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            address: SocketAddr::new(ip, reader.parse("server-port")?),
+            auth_mode,
+            client_uuid_sources,
+            player_info_forwarding,
+            session_server: reader.string("session-server")?.to_owned(),
+            proxy_protocol,
+            proxy_protocol_trusted_sources,
+            authentication_timeout: Duration::from_secs(
+                reader.parse("authentication-timeout-seconds")?,
+            ),
+            login_plugin_timeout: Duration::from_secs(
+                reader.parse("login-plugin-timeout-seconds")?,
+            ),
+            network_compression_threshold,
+            motd: reader.string("motd")?.to_owned(),
+            max_players: reader.parse("max-players")?,
+            favicon_path: PathBuf::from(reader.string("server-icon")?),
+            status_rate_limit,
+            query_port,
+            rcon_port,
+            rcon_password,
+            enforce_secure_profile: reader.parse("enforce-secure-profile")?,
+        })
+    }
+
+    fn to_properties(&self) -> Properties {
+        let mut properties = Properties::default();
+        let mut set = |key: &str, value: &dyn Display| properties.set(key, value.to_string());
+
+        let ip = self.address.ip();
+        set("server-ip", if ip.is_unspecified() { &"" } else { &ip });
+        set("server-port", &self.address.port());
+        set("motd", &self.motd);
+        set("max-players", &self.max_players);
+        set("online-mode", &(self.auth_mode == AuthMode::Online));
+        set(
+            "accept-client-uuids",
+            &(self.auth_mode == AuthMode::TrustedProxy),
+        );
+        set(
+            "client-uuid-sources",
+            &join_addresses(&self.client_uuid_sources),
+        );
+        set("enforce-secure-profile", &self.enforce_secure_profile);
+        set(
+            "network-compression-threshold",
+            &self
+                .network_compression_threshold
+                .map_or(-1, |threshold| i64::try_from(threshold).unwrap_or(i64::MAX)),
+        );
+        set("server-icon", &self.favicon_path.display());
+        set("enable-query", &self.query_port.is_some());
+        set("query.port", &self.query_port.unwrap_or(DEFAULT_QUERY_PORT));
+        set("enable-rcon", &self.rcon_port.is_some());
+        set("rcon.port", &self.rcon_port.unwrap_or(DEFAULT_RCON_PORT));
+        set("rcon.password", &self.rcon_password);
+
+        let (forwarding, secret) = match &self.player_info_forwarding {
+            PlayerInfoForwarding::None => ("none", ""),
+            PlayerInfoForwarding::BungeeCord => ("bungeecord", ""),
+            PlayerInfoForwarding::Velocity { secret } => ("velocity", secret.as_str()),
+        };
+        set("player-info-forwarding", &forwarding);
+        set("velocity-forwarding-secret", &secret);
+        set("proxy-protocol", &self.proxy_protocol);
+        set(
+            "proxy-protocol-trusted-sources",
+            &join_addresses(&self.proxy_protocol_trusted_sources),
+        );
+        set("session-server", &self.session_server);
+        set(
+            "authentication-timeout-seconds",
+            &self.authentication_timeout.as_secs(),
+        );
+        set(
+            "login-plugin-timeout-seconds",
+            &self.login_plugin_timeout.as_secs(),
+        );
+        set(
+            "status-rate-limit-burst",
+            &self.status_rate_limit.map_or(0, |limit| limit.burst),
+        );
+        set(
+            "status-rate-limit-refill-millis",
+            &self.status_rate_limit.map_or(1000, |limit| {
+                u64::try_from(limit.refill.as_millis()).unwrap_or(u64::MAX)
+            }),
+        );
+
+        properties
+    }
+
+    /// What changed between `self` and `new` that only takes effect after a restart
+    pub fn restart_required(&self, new: &Self) -> Vec<&'static str> {
+        [
+            ("server-ip/server-port", self.address != new.address),
+            ("session-server", self.session_server != new.session_server),
+            (
+                "status-rate-limit",
+                self.status_rate_limit != new.status_rate_limit,
+            ),
+            ("query", self.query_port != new.query_port),
+            (
+                "rcon",
+                self.rcon_port != new.rcon_port || self.rcon_password != new.rcon_password,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(name, changed)| changed.then_some(name))
+        .collect()
+    }
+}
+
+fn join_addresses(addresses: &[IpAddr]) -> String {
+    addresses
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Typed access to properties, with errors that say which key was wrong
+struct Reader<'a>(&'a Properties);
+
+impl Reader<'_> {
+    fn string(&self, key: &'static str) -> Result<&str, ConfigError> {
+        self.0.get(key).ok_or_else(|| ConfigError::Invalid {
+            key,
+            value: String::new(),
+            reason: "missing".to_owned(),
+        })
+    }
+
+    fn parse<T>(&self, key: &'static str) -> Result<T, ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let value = self.string(key)?;
+
+        value
+            .trim()
+            .parse()
+            .map_err(|e: T::Err| self.invalid(key, &e.to_string()))
+    }
+
+    /// A comma separated list of IPs, which can be empty
+    fn addresses(&self, key: &'static str) -> Result<Vec<IpAddr>, ConfigError> {
+        self.string(key)?
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .map(|address| {
+                address
+                    .parse()
+                    .map_err(|e| self.invalid(key, &format!("{address}: {e}")))
+            })
+            .collect()
+    }
+
+    fn invalid(&self, key: &'static str, reason: &str) -> ConfigError {
+        ConfigError::Invalid {
+            key,
+            value: self.0.get(key).unwrap_or_default().to_owned(),
+            reason: reason.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(changes: &[(&str, &str)]) -> Result<ServerConfig, ConfigError> {
+        let mut properties = ServerConfig::default().to_properties();
+        for (key, value) in changes {
+            properties.set(key, (*value).to_owned());
+        }
+
+        ServerConfig::from_properties(&properties)
+    }
+
+    #[test]
+    fn client_uuid_sources() {
+        let config = with(&[
+            ("online-mode", "false"),
+            ("accept-client-uuids", "true"),
+            ("client-uuid-sources", "10.0.0.2, ::1"),
+        ])
+        .unwrap();
+        assert_eq!(config.auth_mode, AuthMode::TrustedProxy);
+        assert_eq!(
+            config.client_uuid_sources,
+            [
+                IpAddr::from([10, 0, 0, 2]),
+                IpAddr::from([0, 0, 0, 0, 0, 0, 0, 1])
+            ]
+        );
+        assert_eq!(
+            ServerConfig::from_properties(&config.to_properties()).unwrap(),
+            config
+        );
+
+        // accepting UUIDs from nobody in particular would mean accepting them from everyone
+        assert!(matches!(
+            with(&[("online-mode", "false"), ("accept-client-uuids", "true")]),
+            Err(ConfigError::Invalid {
+                key: "client-uuid-sources",
+                ..
+            })
+        ));
+
+        assert!(matches!(
+            with(&[("client-uuid-sources", "proxy.example.com")]),
+            Err(ConfigError::Invalid {
+                key: "client-uuid-sources",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn proxy_protocol_trusted_sources() {
+        let config = with(&[
+            ("proxy-protocol", "true"),
+            ("proxy-protocol-trusted-sources", "10.0.0.3"),
+        ])
+        .unwrap();
+        assert!(config.proxy_protocol);
+        assert_eq!(
+            config.proxy_protocol_trusted_sources,
+            [IpAddr::from([10, 0, 0, 3])]
+        );
+        assert_eq!(
+            ServerConfig::from_properties(&config.to_properties()).unwrap(),
+            config
+        );
+
+        // every connection would be refused
+        assert!(matches!(
+            with(&[("proxy-protocol", "true")]),
+            Err(ConfigError::Invalid {
+                key: "proxy-protocol-trusted-sources",
+                ..
+            })
+        ));
+
+        // without the header there's nothing to trust them with
+        assert!(with(&[("proxy-protocol", "false")]).is_ok());
+    }
+}
@@ -0,0 +1,183 @@
+//! Everything needed for online-mode encryption
+//!
+//! The server has one RSA keypair for its whole lifetime. During login the
+//! client picks a random shared secret, encrypts it with our public key and
+//! from then on both sides run AES-128 in CFB8 mode over the stream, with the
+//! shared secret as both the key and the IV.
+use std::fmt::{self, Write};
+
+use aes::cipher::{generic_array::GenericArray, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use rsa::{pkcs8::EncodePublicKey, Pkcs1v15Encrypt, RsaPrivateKey};
+use sha1::{Digest, Sha1};
+use tracing::error;
+
+use crate::ProtocolError;
+
+/// Vanilla uses 1024 bit keys, clients don't expect anything else
+const KEY_BITS: usize = 1024;
+
+/// AES-128 needs exactly 16 bytes of key
+const SHARED_SECRET_LENGTH: usize = 16;
+
+pub struct ServerKeys {
+    private_key: RsaPrivateKey,
+    public_key_der: Vec<u8>,
+}
+
+impl ServerKeys {
+    pub fn generate() -> Result<Self, ProtocolError> {
+        let mut rng = rand::thread_rng();
+
+        let private_key = RsaPrivateKey::new(&mut rng, KEY_BITS).map_err(|e| {
+            error!("Couldn't generate RSA keypair: {e:?}");
+            ProtocolError::Encryption
+        })?;
+
+        let public_key_der = private_key
+            .to_public_key()
+            .to_public_key_der()
+            .map_err(|e| {
+                error!("Couldn't encode public key: {e:?}");
+                ProtocolError::Encryption
+            })?
+            .into_vec();
+
+        Ok(Self {
+            private_key,
+            public_key_der,
+        })
+    }
+
+    /// The public key in the ASN.1 DER form sent in the Encryption Request
+    pub fn public_key_der(&self) -> &[u8] {
+        &self.public_key_der
+    }
+
+    /// Undo what the client did with our public key
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+        self.private_key
+            .decrypt(Pkcs1v15Encrypt, data)
+            .map_err(|_| ProtocolError::Encryption)
+    }
+}
+
+/// The `serverId` both the client and the server give to the session server
+///
+/// It's a SHA-1 over the server id, the shared secret and our public key,
+/// printed the way Java's `BigInteger::toString(16)` would: as a signed
+/// number in two's complement, without leading zeros.
+pub fn server_hash(server_id: &str, shared_secret: &[u8], public_key_der: &[u8]) -> String {
+    let mut digest: [u8; 20] = Sha1::new()
+        .chain_update(server_id.as_bytes())
+        .chain_update(shared_secret)
+        .chain_update(public_key_der)
+        .finalize()
+        .into();
+
+    let negative = digest[0] & 0x80 != 0;
+    if negative {
+        // two's complement, flip everything and add one
+        let mut carry = true;
+        for byte in digest.iter_mut().rev() {
+            *byte = !*byte;
+            if carry {
+                (*byte, carry) = byte.overflowing_add(1);
+            }
+        }
+    }
+
+    let hex = digest.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    });
+    let hex = hex.trim_start_matches('0');
+
+    match (negative, hex.is_empty()) {
+        (_, true) => "0".to_owned(),
+        (true, false) => format!("-{hex}"),
+        (false, false) => hex.to_owned(),
+    }
+}
+
+// never let the private key end up in the logs
+impl fmt::Debug for ServerKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerKeys")
+            .field("public_key_der", &self.public_key_der.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// AES/CFB8 state for both directions of a connection
+pub struct StreamCipher {
+    encryptor: cfb8::Encryptor<aes::Aes128>,
+    decryptor: cfb8::Decryptor<aes::Aes128>,
+}
+
+impl StreamCipher {
+    pub fn new(shared_secret: &[u8]) -> Result<Self, ProtocolError> {
+        if shared_secret.len() != SHARED_SECRET_LENGTH {
+            return Err(ProtocolError::Encryption);
+        }
+
+        // the shared secret doubles as the IV
+        let encryptor = cfb8::Encryptor::new_from_slices(shared_secret, shared_secret)
+            .map_err(|_| ProtocolError::Encryption)?;
+        let decryptor = cfb8::Decryptor::new_from_slices(shared_secret, shared_secret)
+            .map_err(|_| ProtocolError::Encryption)?;
+
+        Ok(Self {
+            encryptor,
+            decryptor,
+        })
+    }
+
+    /// CFB8 works on single byte blocks, so it can encrypt in place without padding
+    pub fn encrypt(&mut self, data: &mut [u8]) {
+        for byte in data.chunks_mut(1) {
+            self.encryptor
+                .encrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+
+    pub fn decrypt(&mut self, data: &mut [u8]) {
+        for byte in data.chunks_mut(1) {
+            self.decryptor
+                .decrypt_block_mut(GenericArray::from_mut_slice(byte));
+        }
+    }
+}
+
+impl fmt::Debug for StreamCipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamCipher").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_hash_vectors() {
+        // the examples everyone uses, which are just the name hashed on its own
+        assert_eq!(
+            server_hash("Notch", &[], &[]),
+            "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+        );
+        assert_eq!(
+            server_hash("jeb_", &[], &[]),
+            "-7c9d5b0044c130109a5d7b5fb5c317c02b4e28c1"
+        );
+        assert_eq!(
+            server_hash("simon", &[], &[]),
+            "88e16a1019277b15d58faf0541e11910eb756f6"
+        );
+
+        // it doesn't matter which part of the input the bytes come from
+        assert_eq!(
+            server_hash("", b"Not", b"ch"),
+            "4ed1f46bbe04bc756bcb17c0c7ce3e4632f06a48"
+        );
+    }
+}
@@ -1,8 +1,9 @@
 use std::io::{Read, Write};
 
-use byteorder::{ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
 use tracing::error;
+use uuid::Uuid;
 
 use crate::ProtocolError;
 
@@ -12,7 +13,7 @@ pub trait DataType: Sized {
     fn size(&self) -> usize;
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct VarInt(pub i32);
 
 impl DataType for VarInt {
@@ -51,7 +52,8 @@ impl DataType for VarInt {
     }
 
     fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
-        let mut value = self.0;
+        // shifting the unsigned bits, otherwise negative numbers would never reach 0
+        let mut value = self.0.cast_unsigned();
         let mut bytes = 0;
 
         loop {
@@ -72,7 +74,7 @@ impl DataType for VarInt {
     }
 
     fn size(&self) -> usize {
-        let mut value = self.0;
+        let mut value = self.0.cast_unsigned();
         let mut size = 0;
 
         loop {
@@ -101,10 +103,22 @@ pub struct ProtocolString {
     pub string: String,
 }
 
+impl ProtocolString {
+    /// The longest a string can be, in characters
+    pub const MAX_LENGTH: usize = 32767;
+
+    /// [`Self::MAX_LENGTH`] in bytes, as long as no character takes more than four
+    const MAX_BYTES: usize = Self::MAX_LENGTH * 4;
+}
+
 impl DataType for ProtocolString {
     fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
         let length = VarInt::read_from(reader)?;
         let vec_len = usize::try_from(length.0)?;
+        // the length comes from the client, so it doesn't get to pick how much we allocate
+        if vec_len > Self::MAX_BYTES {
+            return Err(ProtocolError::Malformed);
+        }
 
         let mut vec = vec![0; vec_len];
         reader.read_exact(&mut vec[..])?;
@@ -150,3 +164,815 @@ impl TryFrom<&str> for ProtocolString {
         })
     }
 }
+
+// nothing before play sends one
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VarLong(pub i64);
+
+impl DataType for VarLong {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        let mut result = 0;
+        let mut shift = 0;
+
+        loop {
+            if shift >= 64 {
+                return Err(ProtocolError::Malformed);
+            }
+
+            let byte = reader.read_u8().map_err(|_| ProtocolError::Missing)?;
+            result |= (i64::from(byte & 0x7F)) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        Ok(Self(result))
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        let mut value = self.0.cast_unsigned();
+        let mut bytes = 0;
+
+        loop {
+            let mut temp = u8::try_from(value & 0x7f)?;
+            value >>= 7;
+            bytes += 1;
+
+            if value != 0 {
+                temp |= 0x80;
+            }
+            buffer.write_u8(temp)?;
+
+            if value == 0 {
+                break;
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    fn size(&self) -> usize {
+        let mut value = self.0.cast_unsigned();
+        let mut size = 0;
+
+        loop {
+            value >>= 7;
+            size += 1;
+            if value == 0 {
+                break;
+            }
+        }
+
+        size
+    }
+}
+
+impl DataType for bool {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        match reader.read_u8()? {
+            0x00 => Ok(false),
+            0x01 => Ok(true),
+            _ => Err(ProtocolError::Malformed),
+        }
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        buffer.write_u8(u8::from(*self))?;
+
+        Ok(1)
+    }
+
+    fn size(&self) -> usize {
+        1
+    }
+}
+
+// all fixed width numbers are big endian, so they only differ in which byteorder method to call
+macro_rules! fixed_width {
+    ($($ty:ty => $read:ident, $write:ident;)*) => {
+        $(
+            impl DataType for $ty {
+                fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+                    Ok(reader.$read::<BigEndian>()?)
+                }
+
+                fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+                    buffer.$write::<BigEndian>(*self)?;
+
+                    Ok(self.size())
+                }
+
+                fn size(&self) -> usize {
+                    std::mem::size_of::<$ty>()
+                }
+            }
+        )*
+    };
+}
+
+fixed_width! {
+    i16 => read_i16, write_i16;
+    u16 => read_u16, write_u16;
+    i32 => read_i32, write_i32;
+    i64 => read_i64, write_i64;
+    u64 => read_u64, write_u64;
+    f32 => read_f32, write_f32;
+    f64 => read_f64, write_f64;
+}
+
+impl DataType for i8 {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        Ok(reader.read_i8()?)
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        buffer.write_i8(*self)?;
+
+        Ok(1)
+    }
+
+    fn size(&self) -> usize {
+        1
+    }
+}
+
+impl DataType for u8 {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        Ok(reader.read_u8()?)
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        buffer.write_u8(*self)?;
+
+        Ok(1)
+    }
+
+    fn size(&self) -> usize {
+        1
+    }
+}
+
+/// Sent as two big endian longs, most significant first
+impl DataType for Uuid {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        let mut bytes = [0; 16];
+        reader.read_exact(&mut bytes)?;
+
+        Ok(Self::from_bytes(bytes))
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        buffer.write_all(self.as_bytes())?;
+
+        Ok(16)
+    }
+
+    fn size(&self) -> usize {
+        16
+    }
+}
+
+/// A block position, packed into one long as 26 bits of x, 26 of z and 12 of y
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl Position {
+    const HORIZONTAL_BITS: u32 = 26;
+    const VERTICAL_BITS: u32 = 12;
+
+    /// Whether `value` fits in `bits` bits of two's complement
+    const fn fits(value: i32, bits: u32) -> bool {
+        let limit = 1 << (bits - 1);
+        -limit <= value && value < limit
+    }
+}
+
+impl DataType for Position {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        let packed = reader.read_i64::<BigEndian>()?;
+
+        // shifting left then right again sign extends each part
+        Ok(Self {
+            x: i32::try_from(packed >> 38)?,
+            y: i32::try_from(packed << 52 >> 52)?,
+            z: i32::try_from(packed << 26 >> 38)?,
+        })
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        if !Self::fits(self.x, Self::HORIZONTAL_BITS)
+            || !Self::fits(self.z, Self::HORIZONTAL_BITS)
+            || !Self::fits(self.y, Self::VERTICAL_BITS)
+        {
+            return Err(ProtocolError::Malformed);
+        }
+
+        let packed = ((i64::from(self.x) & 0x3FF_FFFF) << 38)
+            | ((i64::from(self.z) & 0x3FF_FFFF) << 12)
+            | (i64::from(self.y) & 0xFFF);
+        buffer.write_i64::<BigEndian>(packed)?;
+
+        Ok(8)
+    }
+
+    fn size(&self) -> usize {
+        8
+    }
+}
+
+/// A rotation in steps of 1/256 of a full turn
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Angle(pub u8);
+
+#[allow(dead_code)]
+impl Angle {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn from_degrees(degrees: f32) -> Self {
+        // always in 0..256 after the rem_euclid, so the cast can't go wrong
+        Self((degrees.rem_euclid(360.0) * 256.0 / 360.0) as u8)
+    }
+
+    pub fn to_degrees(self) -> f32 {
+        f32::from(self.0) * 360.0 / 256.0
+    }
+}
+
+impl DataType for Angle {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        Ok(Self(reader.read_u8()?))
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        buffer.write_u8(self.0)?;
+
+        Ok(1)
+    }
+
+    fn size(&self) -> usize {
+        1
+    }
+}
+
+/// A namespaced key like `minecraft:stone`, where the namespace defaults to `minecraft`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Identifier {
+    namespace: String,
+    path: String,
+}
+
+impl Identifier {
+    pub const DEFAULT_NAMESPACE: &'static str = "minecraft";
+
+    /// Same limit as every other string, the whole `namespace:path` counts
+    const MAX_LENGTH: usize = ProtocolString::MAX_LENGTH;
+
+    pub fn new(namespace: &str, path: &str) -> Result<Self, ProtocolError> {
+        let valid_namespace = namespace
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '.' | '-' | '_'));
+        let valid_path = path
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '.' | '-' | '_' | '/'));
+
+        if namespace.is_empty()
+            || path.is_empty()
+            || !valid_namespace
+            || !valid_path
+            || namespace.len() + path.len() + 1 > Self::MAX_LENGTH
+        {
+            return Err(ProtocolError::Malformed);
+        }
+
+        Ok(Self {
+            namespace: namespace.to_owned(),
+            path: path.to_owned(),
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    #[allow(dead_code)]
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl std::str::FromStr for Identifier {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((namespace, path)) => Self::new(namespace, path),
+            None => Self::new(Self::DEFAULT_NAMESPACE, s),
+        }
+    }
+}
+
+impl std::fmt::Display for Identifier {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.path)
+    }
+}
+
+impl DataType for Identifier {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        ProtocolString::read_from(reader)?.string.parse()
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        ProtocolString::try_from(self.to_string())?.write_to(buffer)
+    }
+
+    fn size(&self) -> usize {
+        let length = self.namespace.len() + self.path.len() + 1;
+
+        VarInt::try_from(length).map_or(0, |prefix| prefix.size()) + length
+    }
+}
+
+/// Bits packed into longs, prefixed by how many longs there are
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitSet(pub Vec<i64>);
+
+#[allow(dead_code)]
+impl BitSet {
+    pub fn get(&self, index: usize) -> bool {
+        self.0
+            .get(index / 64)
+            .is_some_and(|long| long & (1 << (index % 64)) != 0)
+    }
+
+    pub fn set(&mut self, index: usize, value: bool) {
+        if index / 64 >= self.0.len() {
+            self.0.resize(index / 64 + 1, 0);
+        }
+
+        if value {
+            self.0[index / 64] |= 1 << (index % 64);
+        } else {
+            self.0[index / 64] &= !(1 << (index % 64));
+        }
+    }
+}
+
+impl DataType for BitSet {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        Ok(Self(Vec::read_from(reader)?))
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        self.0.write_to(buffer)
+    }
+
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+}
+
+/// `BITS` bits packed into bytes, without a prefix since both sides know how many there are
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedBitSet<const BITS: usize>(Vec<u8>);
+
+#[allow(dead_code)]
+impl<const BITS: usize> FixedBitSet<BITS> {
+    const BYTES: usize = BITS.div_ceil(8);
+
+    pub fn new() -> Self {
+        Self(vec![0; Self::BYTES])
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        index < BITS && self.0[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    pub fn set(&mut self, index: usize, value: bool) -> Result<(), ProtocolError> {
+        if index >= BITS {
+            return Err(ProtocolError::Malformed);
+        }
+
+        if value {
+            self.0[index / 8] |= 1 << (index % 8);
+        } else {
+            self.0[index / 8] &= !(1 << (index % 8));
+        }
+
+        Ok(())
+    }
+}
+
+impl<const BITS: usize> Default for FixedBitSet<BITS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const BITS: usize> DataType for FixedBitSet<BITS> {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        let mut bytes = vec![0; Self::BYTES];
+        reader.read_exact(&mut bytes)?;
+
+        Ok(Self(bytes))
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        buffer.write_all(&self.0)?;
+
+        Ok(Self::BYTES)
+    }
+
+    fn size(&self) -> usize {
+        Self::BYTES
+    }
+}
+
+/// A boolean saying whether the value follows
+impl<T: DataType> DataType for Option<T> {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        if bool::read_from(reader)? {
+            Ok(Some(T::read_from(reader)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        let mut size = self.is_some().write_to(buffer)?;
+
+        if let Some(value) = self {
+            size += value.write_to(buffer)?;
+        }
+
+        Ok(size)
+    }
+
+    fn size(&self) -> usize {
+        1 + self.as_ref().map_or(0, DataType::size)
+    }
+}
+
+/// How many elements to allocate for up front, so a made up length can't take all the memory
+pub const MAX_PREALLOCATED: usize = 1024;
+
+/// Prefixed by the number of elements, which for `Vec<u8>` makes it a prefixed byte array
+impl<T: DataType> DataType for Vec<T> {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        let length = usize::try_from(VarInt::read_from(reader)?.0)?;
+
+        let mut elements = Self::with_capacity(length.min(MAX_PREALLOCATED));
+        for _ in 0..length {
+            elements.push(T::read_from(reader)?);
+        }
+
+        Ok(elements)
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        let mut size = VarInt::try_from(self.len())?.write_to(buffer)?;
+
+        for element in self {
+            size += element.write_to(buffer)?;
+        }
+
+        Ok(size)
+    }
+
+    fn size(&self) -> usize {
+        let prefix = VarInt::try_from(self.len()).map_or(0, |length| length.size());
+
+        prefix + self.iter().map(DataType::size).sum::<usize>()
+    }
+}
+
+/// Bytes that take up the rest of the packet, so they need no length
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemainingBytes(pub Vec<u8>);
+
+impl DataType for RemainingBytes {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+
+        Ok(Self(bytes))
+    }
+
+    fn write_to<W: Write>(&self, buffer: &mut W) -> Result<usize, ProtocolError> {
+        buffer.write_all(&self.0)?;
+
+        Ok(self.0.len())
+    }
+
+    fn size(&self) -> usize {
+        self.0.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// Writes `value`, checks it comes out as `bytes`, and reads it back from them
+    fn round_trip<T: DataType + PartialEq + Debug>(value: &T, bytes: &[u8]) {
+        let mut written = vec![];
+        let size = value.write_to(&mut written).unwrap();
+
+        assert_eq!(written, bytes, "writing {value:?}");
+        assert_eq!(size, bytes.len(), "size written for {value:?}");
+        assert_eq!(value.size(), bytes.len(), "size of {value:?}");
+
+        let mut reader = bytes;
+        assert_eq!(&T::read_from(&mut reader).unwrap(), value);
+        assert!(reader.is_empty(), "{value:?} left bytes behind");
+    }
+
+    #[test]
+    fn var_int() {
+        round_trip(&VarInt(0), &[0x00]);
+        round_trip(&VarInt(1), &[0x01]);
+        round_trip(&VarInt(127), &[0x7f]);
+        round_trip(&VarInt(128), &[0x80, 0x01]);
+        round_trip(&VarInt(255), &[0xff, 0x01]);
+        round_trip(&VarInt(25565), &[0xdd, 0xc7, 0x01]);
+        round_trip(&VarInt(2_097_151), &[0xff, 0xff, 0x7f]);
+        round_trip(&VarInt(i32::MAX), &[0xff, 0xff, 0xff, 0xff, 0x07]);
+        round_trip(&VarInt(-1), &[0xff, 0xff, 0xff, 0xff, 0x0f]);
+        round_trip(&VarInt(i32::MIN), &[0x80, 0x80, 0x80, 0x80, 0x08]);
+    }
+
+    #[test]
+    fn var_int_too_long_or_cut_off() {
+        let six_bytes = [0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+        assert!(matches!(
+            VarInt::read_from(&mut &six_bytes[..]),
+            Err(ProtocolError::Malformed)
+        ));
+        assert!(matches!(
+            VarInt::read_from(&mut &[0x80, 0x80][..]),
+            Err(ProtocolError::Missing)
+        ));
+    }
+
+    #[test]
+    fn var_long() {
+        round_trip(&VarLong(0), &[0x00]);
+        round_trip(&VarLong(128), &[0x80, 0x01]);
+        round_trip(
+            &VarLong(i64::from(i32::MAX)),
+            &[0xff, 0xff, 0xff, 0xff, 0x07],
+        );
+        round_trip(
+            &VarLong(i64::MAX),
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f],
+        );
+        round_trip(
+            &VarLong(-1),
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+        );
+        round_trip(
+            &VarLong(i64::from(i32::MIN)),
+            &[0x80, 0x80, 0x80, 0x80, 0xf8, 0xff, 0xff, 0xff, 0xff, 0x01],
+        );
+        round_trip(
+            &VarLong(i64::MIN),
+            &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+        );
+
+        let eleven_bytes = [0x80; 11];
+        assert!(matches!(
+            VarLong::read_from(&mut &eleven_bytes[..]),
+            Err(ProtocolError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn fixed_width() {
+        round_trip(&true, &[0x01]);
+        round_trip(&false, &[0x00]);
+        round_trip(&-2_i8, &[0xfe]);
+        round_trip(&200_u8, &[0xc8]);
+        round_trip(&-2_i16, &[0xff, 0xfe]);
+        round_trip(&25565_u16, &[0x63, 0xdd]);
+        round_trip(&-2_i32, &[0xff, 0xff, 0xff, 0xfe]);
+        round_trip(&-2_i64, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe]);
+        round_trip(
+            &(u64::MAX - 1),
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe],
+        );
+        round_trip(&1.5_f32, &[0x3f, 0xc0, 0x00, 0x00]);
+        round_trip(
+            &-0.25_f64,
+            &[0xbf, 0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        );
+
+        assert!(matches!(
+            bool::read_from(&mut &[0x02][..]),
+            Err(ProtocolError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn uuid() {
+        let uuid = Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        round_trip(
+            &uuid,
+            &[
+                0x06, 0x9a, 0x79, 0xf4, 0x44, 0xe9, 0x47, 0x26, 0xa5, 0xbe, 0xfc, 0xa9, 0x0e, 0x38,
+                0xaa, 0xf5,
+            ],
+        );
+    }
+
+    #[test]
+    fn position() {
+        // the example from the protocol docs
+        round_trip(
+            &Position {
+                x: 18_357_644,
+                y: 831,
+                z: -20_882_616,
+            },
+            &[0x46, 0x07, 0x63, 0x2c, 0x15, 0xb4, 0x83, 0x3f],
+        );
+        round_trip(
+            &Position {
+                x: -1,
+                y: -64,
+                z: -33_554_432,
+            },
+            &[0xff, 0xff, 0xff, 0xe0, 0x00, 0x00, 0x0f, 0xc0],
+        );
+
+        let too_high = Position {
+            x: 0,
+            y: 2048,
+            z: 0,
+        };
+        assert!(too_high.write_to(&mut vec![]).is_err());
+        let too_far = Position {
+            x: 33_554_432,
+            y: 0,
+            z: 0,
+        };
+        assert!(too_far.write_to(&mut vec![]).is_err());
+    }
+
+    #[test]
+    fn angle() {
+        round_trip(&Angle(64), &[0x40]);
+        assert_eq!(Angle::from_degrees(90.0), Angle(64));
+        assert_eq!(Angle::from_degrees(-90.0), Angle(192));
+        assert_eq!(Angle::from_degrees(360.0), Angle(0));
+        assert!((Angle(128).to_degrees() - 180.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn identifier() {
+        let stone: Identifier = "stone".parse().unwrap();
+        assert_eq!(stone.namespace(), "minecraft");
+        assert_eq!(stone.path(), "stone");
+
+        let mut bytes = vec![15];
+        bytes.extend_from_slice(b"minecraft:stone");
+        round_trip(&stone, &bytes);
+
+        let channel: Identifier = "velocity:player_info".parse().unwrap();
+        assert_eq!(channel.to_string(), "velocity:player_info");
+        assert!("minecraft:textures/block/stone.png"
+            .parse::<Identifier>()
+            .is_ok());
+
+        for invalid in [
+            "",
+            "Stone",
+            "minecraft:",
+            ":stone",
+            "a:b:c",
+            "with space",
+            "ns/x:y",
+        ] {
+            assert!(
+                invalid.parse::<Identifier>().is_err(),
+                "{invalid:?} was accepted"
+            );
+        }
+        assert!(Identifier::read_from(&mut &b"\x05Stone"[..]).is_err());
+    }
+
+    #[test]
+    fn bit_set() {
+        let mut bits = BitSet::default();
+        bits.set(0, true);
+        bits.set(65, true);
+        assert!(bits.get(65));
+        assert!(!bits.get(64));
+        assert!(!bits.get(1000));
+
+        let mut bytes = vec![0x02];
+        bytes.extend_from_slice(&1_i64.to_be_bytes());
+        bytes.extend_from_slice(&2_i64.to_be_bytes());
+        round_trip(&bits, &bytes);
+
+        bits.set(65, false);
+        assert!(!bits.get(65));
+    }
+
+    #[test]
+    fn fixed_bit_set() {
+        let mut bits = FixedBitSet::<20>::new();
+        bits.set(0, true).unwrap();
+        bits.set(19, true).unwrap();
+        assert!(bits.set(20, true).is_err());
+        assert!(!bits.get(20));
+
+        round_trip(&bits, &[0x01, 0x00, 0x08]);
+    }
+
+    #[test]
+    fn option() {
+        round_trip(&Some(5_i32), &[0x01, 0x00, 0x00, 0x00, 0x05]);
+        round_trip(&None::<i32>, &[0x00]);
+    }
+
+    #[test]
+    fn vec() {
+        round_trip(&vec![1_u16, 2], &[0x02, 0x00, 0x01, 0x00, 0x02]);
+        round_trip(&Vec::<u8>::new(), &[0x00]);
+
+        // a huge length with nothing behind it fails instead of allocating it all
+        assert!(Vec::<u8>::read_from(&mut &[0xff, 0xff, 0xff, 0xff, 0x07][..]).is_err());
+    }
+
+    #[test]
+    fn remaining_bytes() {
+        round_trip(&RemainingBytes(vec![1, 2, 3]), &[1, 2, 3]);
+        round_trip(&RemainingBytes::default(), &[]);
+    }
+
+    #[test]
+    fn protocol_string() {
+        let mut reader = &b"\x05hello"[..];
+        assert_eq!(
+            ProtocolString::read_from(&mut reader).unwrap().string,
+            "hello"
+        );
+
+        let mut written = vec![];
+        ProtocolString::try_from("hello")
+            .unwrap()
+            .write_to(&mut written)
+            .unwrap();
+        assert_eq!(written, b"\x05hello");
+
+        assert!(ProtocolString::read_from(&mut &[0x02, 0xff, 0xfe][..]).is_err());
+    }
+
+    #[test]
+    fn protocol_string_too_long() {
+        // the longest there can be is fine
+        let longest = "\u{10000}".repeat(ProtocolString::MAX_LENGTH);
+        let mut written = vec![];
+        ProtocolString::try_from(longest.as_str())
+            .unwrap()
+            .write_to(&mut written)
+            .unwrap();
+        assert_eq!(
+            ProtocolString::read_from(&mut &written[..]).unwrap().string,
+            longest
+        );
+
+        // a byte more gets refused before anything is read
+        let mut one_more = vec![];
+        VarInt::try_from(ProtocolString::MAX_BYTES + 1)
+            .unwrap()
+            .write_to(&mut one_more)
+            .unwrap();
+        assert!(matches!(
+            ProtocolString::read_from(&mut &one_more[..]),
+            Err(ProtocolError::Malformed)
+        ));
+
+        // the biggest length a VarInt can say, with nothing behind it
+        let huge = [0xff, 0xff, 0xff, 0xff, 0x07];
+        assert!(matches!(
+            ProtocolString::read_from(&mut &huge[..]),
+            Err(ProtocolError::Malformed)
+        ));
+    }
+}
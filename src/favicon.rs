@@ -0,0 +1,209 @@
+//! The server icon shown next to the server in the server list
+//!
+//! Clients only accept a 64x64 PNG, sent inside the status response as a
+//! base64 `data:` URI. Encoding it is done once when loading, not on every ping.
+use std::path::Path;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tracing::{debug, info, warn};
+
+use crate::ProtocolError;
+
+/// What the icon is looked for as when nothing else is configured, same as vanilla
+pub const DEFAULT_FAVICON_PATH: &str = "server-icon.png";
+
+/// Clients refuse to show icons of any other size
+const FAVICON_SIZE: u32 = 64;
+
+const PNG_SIGNATURE: [u8; 8] = *b"\x89PNG\r\n\x1a\n";
+
+/// Load and encode the icon at `path`
+///
+/// A missing file just means there's no icon. Anything else that keeps the
+/// icon from being used is logged, and the server goes on without one.
+pub fn load(path: &Path) -> Option<String> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            debug!("No server icon at {}", path.display());
+            return None;
+        }
+        Err(e) => {
+            warn!("Couldn't read server icon {}: {e}", path.display());
+            return None;
+        }
+    };
+
+    match encode(&bytes) {
+        Ok(favicon) => {
+            info!("Loaded server icon from {}", path.display());
+            Some(favicon)
+        }
+        Err(e) => {
+            warn!("Not using server icon {}: {e}", path.display());
+            None
+        }
+    }
+}
+
+/// Check that `png` is a 64x64 PNG and turn it into a data URI
+pub fn encode(png: &[u8]) -> Result<String, ProtocolError> {
+    let (width, height) = png_dimensions(png)?;
+    if (width, height) != (FAVICON_SIZE, FAVICON_SIZE) {
+        return Err(ProtocolError::Favicon(format!(
+            "it's {width}x{height}, it has to be {FAVICON_SIZE}x{FAVICON_SIZE}"
+        )));
+    }
+
+    Ok(format!("data:image/png;base64,{}", STANDARD.encode(png)))
+}
+
+/// The size from the IHDR chunk, which the PNG spec says has to come right after the signature
+fn png_dimensions(png: &[u8]) -> Result<(u32, u32), ProtocolError> {
+    if !png.starts_with(&PNG_SIGNATURE) {
+        return Err(ProtocolError::Favicon("it's not a PNG".to_owned()));
+    }
+
+    // 4 bytes of chunk length, the chunk type, then width and height
+    let Some(ihdr) = png.get(PNG_SIGNATURE.len()..PNG_SIGNATURE.len() + 16) else {
+        return Err(ProtocolError::Favicon("it's cut short".to_owned()));
+    };
+    if &ihdr[4..8] != b"IHDR" {
+        return Err(ProtocolError::Favicon(
+            "it doesn't start with an IHDR chunk".to_owned(),
+        ));
+    }
+
+    let width = u32::from_be_bytes([ihdr[8], ihdr[9], ihdr[10], ihdr[11]]);
+    let height = u32::from_be_bytes([ihdr[12], ihdr[13], ihdr[14], ihdr[15]]);
+
+    Ok((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::ZlibEncoder, Compression, Crc};
+
+    use super::*;
+
+    fn chunk(png: &mut Vec<u8>, kind: [u8; 4], data: &[u8]) {
+        png.extend_from_slice(&u32::try_from(data.len()).unwrap().to_be_bytes());
+
+        let mut crc = Crc::new();
+        crc.update(&kind);
+        crc.update(data);
+
+        png.extend_from_slice(&kind);
+        png.extend_from_slice(data);
+        png.extend_from_slice(&crc.sum().to_be_bytes());
+    }
+
+    /// A black 8-bit grayscale PNG
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut ihdr = vec![];
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+        // every row starts with its filter type, none here
+        let row = usize::try_from(width).unwrap() + 1;
+        let pixels = vec![0; row * usize::try_from(height).unwrap()];
+        let mut idat = ZlibEncoder::new(vec![], Compression::default());
+        idat.write_all(&pixels).unwrap();
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        chunk(&mut png, *b"IHDR", &ihdr);
+        chunk(&mut png, *b"IDAT", &idat.finish().unwrap());
+        chunk(&mut png, *b"IEND", &[]);
+        png
+    }
+
+    /// Somewhere only this test writes to
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("copper-{}-{name}.png", std::process::id()))
+    }
+
+    #[test]
+    fn encode_valid() {
+        let png = png(64, 64);
+        assert_eq!(png_dimensions(&png).unwrap(), (64, 64));
+
+        let favicon = encode(&png).unwrap();
+        let base64 = favicon.strip_prefix("data:image/png;base64,").unwrap();
+        assert_eq!(STANDARD.decode(base64).unwrap(), png);
+    }
+
+    #[test]
+    fn wrong_dimensions() {
+        assert_eq!(png_dimensions(&png(128, 32)).unwrap(), (128, 32));
+
+        for (width, height) in [(128, 128), (64, 63), (1, 64)] {
+            assert!(
+                matches!(encode(&png(width, height)), Err(ProtocolError::Favicon(_))),
+                "{width}x{height}"
+            );
+        }
+    }
+
+    #[test]
+    fn not_a_png() {
+        let mut jpeg = png(64, 64);
+        jpeg[..4].copy_from_slice(&[0xff, 0xd8, 0xff, 0xe0]);
+
+        for bytes in [&[][..], b"\x89PNG", &jpeg] {
+            assert!(
+                matches!(png_dimensions(bytes), Err(ProtocolError::Favicon(_))),
+                "{bytes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_ihdr() {
+        let png = png(64, 64);
+
+        // the height is the last thing needed
+        for length in [
+            PNG_SIGNATURE.len(),
+            PNG_SIGNATURE.len() + 8,
+            PNG_SIGNATURE.len() + 15,
+        ] {
+            assert!(
+                matches!(
+                    png_dimensions(&png[..length]),
+                    Err(ProtocolError::Favicon(_))
+                ),
+                "{length} bytes"
+            );
+        }
+        assert!(png_dimensions(&png[..PNG_SIGNATURE.len() + 16]).is_ok());
+
+        // some other chunk first
+        let mut png = png;
+        png[PNG_SIGNATURE.len() + 4..PNG_SIGNATURE.len() + 8].copy_from_slice(b"tEXt");
+        assert!(matches!(
+            png_dimensions(&png),
+            Err(ProtocolError::Favicon(_))
+        ));
+    }
+
+    #[test]
+    fn load_from_file() {
+        let path = temp_path("valid");
+        std::fs::write(&path, png(64, 64)).unwrap();
+        let favicon = load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(favicon, Some(encode(&png(64, 64)).unwrap()));
+
+        // a bad icon doesn't stop the server, there's just none
+        let path = temp_path("too-big");
+        std::fs::write(&path, png(128, 128)).unwrap();
+        let favicon = load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(favicon, None);
+
+        assert_eq!(load(&temp_path("missing")), None);
+    }
+}
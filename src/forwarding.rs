@@ -0,0 +1,374 @@
+//! Taking the player's real identity from a proxy in front of us
+//!
+//! A proxy connects to us on the player's behalf, so without forwarding every
+//! player would seem to come from the proxy's address, and in offline mode
+//! they'd all get offline UUIDs instead of their real ones.
+use std::{io::Cursor, net::IpAddr};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tracing::{trace, warn};
+use uuid::Uuid;
+
+use crate::{
+    auth::{GameProfile, ProfileProperty},
+    data_types::{DataType, ProtocolString, VarInt},
+    login::Property,
+    ProtocolError,
+};
+
+/// The login plugin channel Velocity answers with the player's info
+pub const VELOCITY_CHANNEL: &str = "velocity:player_info";
+
+/// The oldest forwarding version, only carrying the address and profile
+const VELOCITY_MODERN_DEFAULT: u8 = 1;
+
+/// Length of the HMAC-SHA256 signature in front of the forwarded data
+const SIGNATURE_LENGTH: usize = 32;
+
+/// Who the proxy says is really behind a connection
+#[derive(Debug)]
+pub struct ForwardedPlayer {
+    pub ip: IpAddr,
+    pub profile: GameProfile,
+}
+
+/// What BungeeCord puts in the handshake, everything but the name which comes with `LoginStart`
+#[derive(Debug)]
+pub struct BungeeCordForwarding {
+    pub ip: IpAddr,
+    pub uuid: Uuid,
+    pub properties: Vec<ProfileProperty>,
+}
+
+impl BungeeCordForwarding {
+    pub fn into_player(self, name: String) -> ForwardedPlayer {
+        ForwardedPlayer {
+            ip: self.ip,
+            profile: GameProfile {
+                uuid: self.uuid,
+                name,
+                properties: self.properties,
+            },
+        }
+    }
+}
+
+/// Read the forwarded data out of a handshake's server address
+///
+/// With `ip_forward` on, BungeeCord sends `host\0ip\0uuid\0properties` instead
+/// of just the host, where the properties (a JSON array) only show up when
+/// the proxy is in online mode. `None` means the address is just a host.
+pub fn read_bungeecord_address(
+    server_address: &str,
+) -> Result<Option<BungeeCordForwarding>, ProtocolError> {
+    let mut parts = server_address.split('\0');
+    let (Some(host), Some(ip), Some(uuid)) = (parts.next(), parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    trace!("BungeeCord forwarding for host {host:?}");
+
+    let ip = ip.parse().map_err(|_| ProtocolError::Malformed)?;
+    let uuid = Uuid::parse_str(uuid).map_err(|_| ProtocolError::Malformed)?;
+    let properties = match parts.next() {
+        Some(properties) => serde_json::from_str(properties)?,
+        None => vec![],
+    };
+
+    Ok(Some(BungeeCordForwarding {
+        ip,
+        uuid,
+        properties,
+    }))
+}
+
+/// What we ask Velocity for, which is the highest version we know how to read
+pub fn velocity_request() -> Vec<u8> {
+    vec![VELOCITY_MODERN_DEFAULT]
+}
+
+/// Check and read Velocity's answer to a `velocity:player_info` request
+///
+/// The answer is a signature followed by the data it signs, where the
+/// signature is an HMAC-SHA256 keyed with the secret shared with the proxy.
+/// Without it anyone could connect straight to us and claim to be anyone.
+pub fn read_velocity_response(
+    secret: &[u8],
+    response: &[u8],
+) -> Result<ForwardedPlayer, ProtocolError> {
+    if response.len() < SIGNATURE_LENGTH {
+        return Err(ProtocolError::Missing);
+    }
+    let (signature, data) = response.split_at(SIGNATURE_LENGTH);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).map_err(|_| ProtocolError::Internal)?;
+    mac.update(data);
+    if mac.verify_slice(signature).is_err() {
+        warn!("Velocity forwarding data has a bad signature, check the forwarding secret");
+        return Err(ProtocolError::Disconnect(
+            "Unable to verify player details".to_owned(),
+        ));
+    }
+
+    let mut reader = Cursor::new(data);
+
+    let VarInt(version) = VarInt::read_from(&mut reader)?;
+    trace!("Velocity forwarding version: {version}");
+    if version < i32::from(VELOCITY_MODERN_DEFAULT) {
+        return Err(ProtocolError::Malformed);
+    }
+
+    let address = ProtocolString::read_from(&mut reader)?;
+    let ip = address
+        .string
+        .parse()
+        .map_err(|_| ProtocolError::Malformed)?;
+
+    let uuid = Uuid::read_from(&mut reader)?;
+
+    let name = ProtocolString::read_from(&mut reader)?.string;
+
+    let properties = Vec::<Property>::read_from(&mut reader)?
+        .into_iter()
+        .map(ProfileProperty::from)
+        .collect();
+
+    Ok(ForwardedPlayer {
+        ip,
+        profile: GameProfile {
+            uuid,
+            name,
+            properties,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"forwarding secret";
+
+    fn uuid() -> Uuid {
+        Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap()
+    }
+
+    /// What Velocity sends for Notch connecting from `address`, before signing
+    fn velocity_data(version: i32, address: &str) -> Vec<u8> {
+        let properties = vec![
+            Property {
+                name: "textures".try_into().unwrap(),
+                value: "e30=".try_into().unwrap(),
+                is_signed: true,
+                signature: Some("c2ln".try_into().unwrap()),
+            },
+            Property {
+                name: "unsigned".try_into().unwrap(),
+                value: "".try_into().unwrap(),
+                is_signed: false,
+                signature: None,
+            },
+        ];
+
+        let mut data = vec![];
+        VarInt(version).write_to(&mut data).unwrap();
+        ProtocolString::try_from(address)
+            .unwrap()
+            .write_to(&mut data)
+            .unwrap();
+        uuid().write_to(&mut data).unwrap();
+        ProtocolString::try_from("Notch")
+            .unwrap()
+            .write_to(&mut data)
+            .unwrap();
+        properties.write_to(&mut data).unwrap();
+
+        data
+    }
+
+    fn sign(secret: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+        mac.update(data);
+
+        let mut response = mac.finalize().into_bytes().to_vec();
+        response.extend_from_slice(data);
+        response
+    }
+
+    /// A handshake address the way BungeeCord puts it together
+    fn bungeecord(fields: &[&str]) -> String {
+        fields.join("\0")
+    }
+
+    #[test]
+    fn bungeecord_address() {
+        let address = bungeecord(&[
+            "mc.example.com",
+            "203.0.113.7",
+            "069a79f444e94726a5befca90e38aaf5",
+        ]);
+        let forwarding = read_bungeecord_address(&address).unwrap().unwrap();
+        assert_eq!(forwarding.ip, IpAddr::from([203, 0, 113, 7]));
+        assert_eq!(forwarding.uuid, uuid());
+        assert!(forwarding.properties.is_empty());
+
+        let player = forwarding.into_player("Notch".to_owned());
+        assert_eq!(player.profile.name, "Notch");
+        assert_eq!(player.profile.uuid, uuid());
+    }
+
+    #[test]
+    fn bungeecord_address_with_properties() {
+        let address = bungeecord(&[
+            "mc.example.com",
+            "2001:db8::1",
+            "069a79f4-44e9-4726-a5be-fca90e38aaf5",
+            r#"[{"name":"textures","value":"e30=","signature":"c2ln"}]"#,
+        ]);
+        let forwarding = read_bungeecord_address(&address).unwrap().unwrap();
+        assert_eq!(forwarding.ip, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(forwarding.uuid, uuid());
+
+        let [property] = &forwarding.properties[..] else {
+            panic!("expected one property, got {:?}", forwarding.properties);
+        };
+        assert_eq!(property.name, "textures");
+        assert_eq!(property.value, "e30=");
+        assert_eq!(property.signature.as_deref(), Some("c2ln"));
+    }
+
+    #[test]
+    fn bungeecord_address_not_forwarded() {
+        // a plain host, or one that stops short of the UUID, isn't forwarding
+        for address in [
+            bungeecord(&["mc.example.com"]),
+            bungeecord(&["mc.example.com", "203.0.113.7"]),
+        ] {
+            assert!(read_bungeecord_address(&address).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn bungeecord_address_invalid() {
+        let uuid = "069a79f444e94726a5befca90e38aaf5";
+        for fields in [
+            ["mc.example.com", "", uuid],
+            ["mc.example.com", "not an ip", uuid],
+            ["mc.example.com", "203.0.113.7", ""],
+            ["mc.example.com", "203.0.113.7", &uuid[1..]],
+            ["mc.example.com", "203.0.113.7", "Notch"],
+        ] {
+            assert!(
+                matches!(
+                    read_bungeecord_address(&bungeecord(&fields)),
+                    Err(ProtocolError::Malformed)
+                ),
+                "{fields:?}"
+            );
+        }
+
+        for properties in ["", "{}", r#"[{"name":"textures"}]"#, "[{"] {
+            let address = bungeecord(&["mc.example.com", "203.0.113.7", uuid, properties]);
+            assert!(
+                matches!(
+                    read_bungeecord_address(&address),
+                    Err(ProtocolError::SerdeJson(_))
+                ),
+                "{properties:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn velocity_request_asks_for_the_default_version() {
+        assert_eq!(velocity_request(), [VELOCITY_MODERN_DEFAULT]);
+    }
+
+    #[test]
+    fn velocity_response() {
+        let response = sign(SECRET, &velocity_data(1, "203.0.113.7"));
+        let player = read_velocity_response(SECRET, &response).unwrap();
+
+        assert_eq!(player.ip, IpAddr::from([203, 0, 113, 7]));
+        assert_eq!(player.profile.uuid, uuid());
+        assert_eq!(player.profile.name, "Notch");
+
+        let properties: Vec<_> = player
+            .profile
+            .properties
+            .iter()
+            .map(|property| {
+                (
+                    property.name.as_str(),
+                    property.value.as_str(),
+                    property.signature.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            properties,
+            [("textures", "e30=", Some("c2ln")), ("unsigned", "", None)]
+        );
+    }
+
+    #[test]
+    fn velocity_response_ipv6() {
+        let response = sign(SECRET, &velocity_data(1, "2001:db8::1"));
+        let player = read_velocity_response(SECRET, &response).unwrap();
+
+        assert_eq!(player.ip, "2001:db8::1".parse::<IpAddr>().unwrap());
+
+        // not an address at all
+        let response = sign(SECRET, &velocity_data(1, "localhost"));
+        assert!(matches!(
+            read_velocity_response(SECRET, &response),
+            Err(ProtocolError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn velocity_response_wrong_secret() {
+        let response = sign(b"someone else's secret", &velocity_data(1, "203.0.113.7"));
+
+        assert!(matches!(
+            read_velocity_response(SECRET, &response),
+            Err(ProtocolError::Disconnect(_))
+        ));
+
+        // changing the data after signing is just as bad
+        let mut response = sign(SECRET, &velocity_data(1, "203.0.113.7"));
+        let last = response.len() - 1;
+        response[last] ^= 1;
+        assert!(matches!(
+            read_velocity_response(SECRET, &response),
+            Err(ProtocolError::Disconnect(_))
+        ));
+    }
+
+    #[test]
+    fn velocity_response_truncated() {
+        // not even a whole signature
+        assert!(matches!(
+            read_velocity_response(SECRET, &[0; SIGNATURE_LENGTH - 1]),
+            Err(ProtocolError::Missing)
+        ));
+
+        // properly signed, but cut off in the middle of the UUID
+        let mut data = velocity_data(1, "203.0.113.7");
+        data.truncate(1 + 12 + 8);
+        assert!(matches!(
+            read_velocity_response(SECRET, &sign(SECRET, &data)),
+            Err(ProtocolError::IOError(_))
+        ));
+    }
+
+    #[test]
+    fn velocity_response_unsupported_version() {
+        let response = sign(SECRET, &velocity_data(0, "203.0.113.7"));
+
+        assert!(matches!(
+            read_velocity_response(SECRET, &response),
+            Err(ProtocolError::Malformed)
+        ));
+    }
+}
@@ -1,57 +1,49 @@
-use byteorder::{BigEndian, ReadBytesExt};
-use std::io::{Cursor, Read};
+use byteorder::{BigEndian, WriteBytesExt};
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tracing::trace;
 
 use crate::{
     data_types::{DataType, ProtocolString, VarInt},
-    packet::Decodable,
+    packet::Packet,
+    server_status::ServerStatus,
     ProtocolError, State,
 };
 
 #[derive(Debug)]
 pub enum ServerBound {
     Handshake(Handshake),
-    // TODO
-    Legacy(Legacy),
 }
 
-impl Decodable for ServerBound {
-    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
-        let VarInt(length) = VarInt::read_from(reader)?;
-        let length = usize::try_from(length)?;
-
-        let packet_id = VarInt::read_from(reader)?;
-
-        trace!("Handshaking Packet ID: {:?}", packet_id);
-
-        let buffer_size = length - packet_id.size();
-        trace!("Buffer Size: {}", buffer_size);
-        let mut buffer = vec![0; buffer_size];
-
-        reader.read_exact(&mut buffer[..])?;
-        trace!("Buffer: {:?}", buffer);
+#[derive(Debug, Clone, Copy)]
+pub enum NextState {
+    Status = 1,
+    Login = 2,
+}
 
-        if buffer.len() != buffer_size {
-            return Err(ProtocolError::Malformed);
+impl DataType for NextState {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        match VarInt::read_from(reader)? {
+            VarInt(1) => Ok(Self::Status),
+            VarInt(2) => Ok(Self::Login),
+            VarInt(_) => Err(ProtocolError::Malformed),
         }
+    }
 
-        let mut cursor = Cursor::new(buffer);
-
-        match packet_id {
-            VarInt(0x00) => Ok(Self::Handshake(Handshake::read_from(&mut cursor)?)),
-            VarInt(0xFE) => Ok(Self::Legacy(Legacy::read_from(&mut cursor)?)),
-            VarInt(n) => Err(ProtocolError::PacketId(n)),
-        }
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<usize, ProtocolError> {
+        VarInt(*self as i32).write_to(writer)
     }
-}
 
-#[derive(Debug, Clone)]
-pub enum NextState {
-    Status = 1,
-    Login = 2,
+    fn size(&self) -> usize {
+        VarInt(*self as i32).size()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Packet)]
+#[packet(id = 0x00, state = Handshaking, direction = ServerBound)]
 #[allow(unused)]
 pub struct Handshake {
     protocol_version: VarInt,
@@ -61,28 +53,13 @@ pub struct Handshake {
 }
 
 impl Handshake {
-    fn read_from<R>(reader: &mut R) -> Result<Self, ProtocolError>
-    where
-        R: Read,
-    {
-        let protocol_version = VarInt::read_from(reader)?;
-        trace!("Protocol Version: {:?}", protocol_version);
-        let server_address = ProtocolString::read_from(reader)?;
-        trace!("Server Address: {:?}", protocol_version);
-        let server_port = reader.read_u16::<BigEndian>()?;
-        trace!("Server Port: {:?}", protocol_version);
-        let next_state = match VarInt::read_from(reader)? {
-            VarInt(1) => NextState::Status,
-            VarInt(2) => NextState::Login,
-            VarInt(_) => return Err(ProtocolError::Malformed),
-        };
+    pub const fn protocol_version(&self) -> i32 {
+        self.protocol_version.0
+    }
 
-        Ok(Self {
-            protocol_version,
-            server_address,
-            server_port,
-            next_state,
-        })
+    /// The address the client used to connect, unless a proxy stuffed more into it
+    pub fn server_address(&self) -> &str {
+        &self.server_address.string
     }
 
     pub const fn get_next_state(&self) -> State {
@@ -93,18 +70,265 @@ impl Handshake {
     }
 }
 
-#[derive(Debug)]
-pub struct Legacy {
-    pub payload: u8,
+/// The first byte of every ping from before 1.7, which didn't use `VarInt` framing yet
+///
+/// A modern handshake can start with it too, as the first byte of a length
+/// prefix of 254, 382, 510 and so on, so it's never enough on its own.
+pub const LEGACY_PING: u8 = 0xFE;
+
+/// How long to wait for the rest of a legacy ping after the first byte
+const LEGACY_PING_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// What 1.6 sends after `0xFE 0x01`, a plugin message (`0xFA`) on the `MC|PingHost`
+/// channel, whose name is UTF-16 prefixed by its length in characters
+const PING_HOST: &[u8] = b"\xFA\x00\x0B\x00M\x00C\x00|\x00P\x00i\x00n\x00g\x00H\x00o\x00s\x00t";
+
+/// Which of the pre-netty server list pings a client sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyPing {
+    /// Beta 1.8 to 1.3, nothing but `0xFE`
+    Beta,
+    /// 1.4 to 1.6, `0xFE 0x01`, with 1.6 also sending a `MC|PingHost` plugin message after it
+    Release,
 }
 
-impl Legacy {
-    fn read_from<R>(reader: &mut R) -> Result<Self, ProtocolError>
+/// What the bytes read so far say about the connection
+#[derive(Debug, PartialEq, Eq)]
+enum Detection {
+    Ping(LegacyPing),
+    Modern,
+    /// Could still go either way
+    Incomplete,
+}
+
+impl LegacyPing {
+    /// Read until it's clear whether the client sent a legacy ping, like vanilla's `LegacyQueryHandler`
+    ///
+    /// Only a lone `0xFE`, `0xFE 0x01` with nothing after it, or `0xFE 0x01 0xFA`
+    /// followed by the `MC|PingHost` channel count. Everything taken out of
+    /// `reader` ends up in `read`, so it can still go to the codec when the
+    /// answer is `None`.
+    pub async fn read_from<R>(
+        reader: &mut R,
+        read: &mut Vec<u8>,
+    ) -> Result<Option<Self>, ProtocolError>
     where
-        R: Read,
+        R: AsyncRead + Unpin,
     {
-        let payload = reader.read_u8()?;
+        loop {
+            match Self::detect(read) {
+                Detection::Ping(ping) => return Ok(Some(ping)),
+                Detection::Modern => return Ok(None),
+                Detection::Incomplete => {}
+            }
+
+            let mut chunk = [0; 256];
+            let received = if read.is_empty() {
+                reader.read(&mut chunk).await?
+            } else {
+                // old clients send their ping and wait, so going quiet means it's all there
+                match tokio::time::timeout(LEGACY_PING_TIMEOUT, reader.read(&mut chunk)).await {
+                    Ok(received) => received?,
+                    Err(_) => 0,
+                }
+            };
+
+            if received == 0 {
+                trace!("Legacy ping candidate went quiet after {read:?}");
+
+                return Ok(match read.as_slice() {
+                    [LEGACY_PING] => Some(Self::Beta),
+                    [LEGACY_PING, 0x01] => Some(Self::Release),
+                    _ => None,
+                });
+            }
+
+            read.extend_from_slice(&chunk[..received]);
+        }
+    }
+
+    fn detect(bytes: &[u8]) -> Detection {
+        match bytes {
+            [] | [LEGACY_PING] | [LEGACY_PING, 0x01] => Detection::Incomplete,
+            [LEGACY_PING, 0x01, rest @ ..] if rest.starts_with(PING_HOST) => {
+                Detection::Ping(Self::Release)
+            }
+            [LEGACY_PING, 0x01, rest @ ..] if PING_HOST.starts_with(rest) => Detection::Incomplete,
+            _ => Detection::Modern,
+        }
+    }
+}
+
+/// The answer to a legacy ping, which is a kick packet with the server info as the reason
+#[derive(Debug)]
+pub struct LegacyKick {
+    reason: String,
+}
+
+impl LegacyKick {
+    /// Protocol version sent to clients from before 1.7, which no longer matches any of them
+    const PROTOCOL_VERSION: u8 = 127;
+
+    pub fn new(ping: LegacyPing, status: &ServerStatus) -> Self {
+        let reason = match ping {
+            LegacyPing::Beta => format!(
+                "{}\u{a7}{}\u{a7}{}",
+                status.description().to_plain(),
+                status.online_players(),
+                status.max_players()
+            ),
+            LegacyPing::Release => format!(
+                "\u{a7}1\0{}\0{}\0{}\0{}\0{}",
+                Self::PROTOCOL_VERSION,
+                status.version_name(),
+                status.description().to_legacy(),
+                status.online_players(),
+                status.max_players()
+            ),
+        };
+
+        Self { reason }
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<usize, ProtocolError> {
+        let reason: Vec<u16> = self.reason.encode_utf16().collect();
+
+        let mut response = vec![0xFF];
+        // the length is in characters (UTF-16 code units), not bytes
+        response.write_u16::<BigEndian>(u16::try_from(reason.len())?)?;
+        for unit in reason {
+            response.write_u16::<BigEndian>(unit)?;
+        }
+
+        writer.write_all(&response)?;
+
+        Ok(response.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::codec::Decoder;
+
+    use super::*;
+    use crate::{
+        codec::FrameCodec,
+        packet::{self, Encodable},
+    };
+
+    /// A framed handshake with an address padded out so the frame is `length` bytes,
+    /// which has to be enough for the address to need a two byte prefix
+    fn handshake_frame(length: usize) -> Vec<u8> {
+        // id, protocol version, address length prefix, port and next state
+        let address = "x".repeat(length - 1 - 2 - 2 - 2 - 1);
+        let handshake = Handshake {
+            protocol_version: VarInt(crate::PROTOCOL_VERSION),
+            server_address: ProtocolString::try_from(address).unwrap(),
+            server_port: 25565,
+            next_state: NextState::Login,
+        };
+
+        let mut body = vec![];
+        handshake.write_to(&mut body).unwrap();
+        assert_eq!(body.len(), length);
+
+        let mut frame = vec![];
+        VarInt::try_from(length)
+            .unwrap()
+            .write_to(&mut frame)
+            .unwrap();
+        frame.extend_from_slice(&body);
+        frame
+    }
+
+    /// What detection made of `bytes`, with the ones it read and the ones it left
+    async fn detect(bytes: &[u8]) -> (Option<LegacyPing>, Vec<u8>, &[u8]) {
+        let mut reader = bytes;
+        let mut read = vec![];
+        let ping = LegacyPing::read_from(&mut reader, &mut read).await.unwrap();
+        (ping, read, reader)
+    }
+
+    #[tokio::test]
+    async fn handshake_of_254_bytes_is_not_a_legacy_ping() {
+        let frame = handshake_frame(254);
+        assert_eq!(frame[..3], [LEGACY_PING, 0x01, 0x00]);
+
+        let (ping, read, rest) = detect(&frame).await;
+        assert_eq!(ping, None);
+
+        // what was read goes to the codec first, then whatever is still in the socket
+        let mut buffer = BytesMut::from(&read[..]);
+        buffer.extend_from_slice(rest);
+        let decoded = FrameCodec::new().decode(&mut buffer).unwrap().unwrap();
+
+        let mut reader = &decoded[..];
+        assert_eq!(VarInt::read_from(&mut reader).unwrap(), VarInt(0x00));
+        let handshake = packet::decode::<Handshake, _>(&mut reader).unwrap();
+        assert_eq!(handshake.server_address().len(), 254 - 8);
+        assert_eq!(handshake.get_next_state(), State::Login);
+    }
+
+    #[tokio::test]
+    async fn other_lengths_starting_with_0xfe_are_not_legacy_pings() {
+        for length in [382, 510, 638] {
+            let frame = handshake_frame(length);
+            assert_eq!(frame[0], LEGACY_PING);
+
+            let (ping, read, rest) = detect(&frame).await;
+            assert_eq!(ping, None);
+            assert_eq!([read.as_slice(), rest].concat(), frame);
+        }
+    }
+
+    #[tokio::test]
+    async fn modern_handshake() {
+        let frame = handshake_frame(200);
+
+        let (ping, read, _) = detect(&frame).await;
+        assert_eq!(ping, None);
+        assert_eq!(read, frame);
+    }
+
+    #[tokio::test]
+    async fn beta_ping() {
+        assert_eq!(detect(&[LEGACY_PING]).await.0, Some(LegacyPing::Beta));
+    }
+
+    #[tokio::test]
+    async fn release_ping() {
+        assert_eq!(
+            detect(&[LEGACY_PING, 0x01]).await.0,
+            Some(LegacyPing::Release)
+        );
+    }
+
+    #[tokio::test]
+    async fn ping_host() {
+        // what a 1.6.4 client sends for localhost:25565
+        let mut ping = vec![LEGACY_PING, 0x01];
+        ping.extend_from_slice(PING_HOST);
+        ping.extend_from_slice(&[0x00, 0x17, 0x4e, 0x00, 0x09]);
+        ping.extend("localhost".encode_utf16().flat_map(u16::to_be_bytes));
+        ping.extend_from_slice(&25565_i32.to_be_bytes());
+
+        assert_eq!(detect(&ping).await.0, Some(LegacyPing::Release));
+
+        // cut off in the middle of the channel name it's nothing we know
+        assert_eq!(detect(&ping[..10]).await.0, None);
+    }
+
+    #[tokio::test]
+    async fn beta_ping_waits_for_the_rest() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&[LEGACY_PING]).await.unwrap();
 
-        Ok(Self { payload })
+        // the client keeps the connection open, waiting for an answer
+        let mut read = vec![];
+        let ping = LegacyPing::read_from(&mut server, &mut read).await.unwrap();
+        assert_eq!(ping, Some(LegacyPing::Beta));
+        drop(client);
     }
 }
@@ -1,13 +1,12 @@
-use std::io::{Cursor, Read, Write};
+use std::io::{Read, Write};
 
-use byteorder::ReadBytesExt;
-
-use tracing::trace;
 use uuid::Uuid;
 
 use crate::{
-    data_types::{DataType, ProtocolString, VarInt},
-    packet::Decodable,
+    auth::ProfileProperty,
+    chat::TextComponent,
+    data_types::{DataType, Identifier, ProtocolString, RemainingBytes, VarInt},
+    packet::{Encodable, Packet},
     ProtocolError,
 };
 
@@ -36,135 +35,160 @@ impl ClientBound {
     {
         match self {
             Self::Disconnect(packet) => packet.write_to(writer),
-            Self::EncryptionRequest(_packet) => {
-                trace!("unimplemented");
-                Err(ProtocolError::Unimplemented)
-            }
+            Self::EncryptionRequest(packet) => packet.write_to(writer),
             Self::LoginSuccess(packet) => packet.write_to(writer),
-            Self::SetCompression(_packet) => {
-                trace!("unimplemented");
-                Err(ProtocolError::Unimplemented)
-            }
-            Self::LoginPluginRequest(_packet) => {
-                trace!("unimplemented");
-                Err(ProtocolError::Unimplemented)
-            }
-        }
-    }
-
-    pub fn from_request(request: ServerBound) -> Result<Self, ProtocolError> {
-        match request {
-            ServerBound::LoginStart(req) => Ok(Self::LoginSuccess(LoginSuccess {
-                // TODO dont do this
-                uuid: req.player_uuid.unwrap_or_default(),
-                username: req.name,
-                number_of_properties: VarInt(0),
-                property: vec![],
-            })),
-            _ => Err(ProtocolError::Unimplemented),
+            Self::SetCompression(packet) => packet.write_to(writer),
+            Self::LoginPluginRequest(packet) => packet.write_to(writer),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Packet)]
+#[packet(id = 0x00, state = Login, direction = ClientBound)]
 pub struct Disconnect {
     reason: ProtocolString,
 }
 
 impl Disconnect {
-    pub const fn new(reason: ProtocolString) -> Self {
-        Self { reason }
-    }
-
-    fn write_to<W>(&self, writer: &mut W) -> Result<usize, ProtocolError>
-    where
-        W: Write,
-    {
-        let mut response = vec![];
-        let packet_id = VarInt(0x00);
-        let packet_length = VarInt(i32::try_from(packet_id.size() + self.reason.size())?);
-
-        packet_length.write_to(&mut response)?;
-        packet_id.write_to(&mut response)?;
-        self.reason.write_to(&mut response)?;
-
-        writer.write_all(&response)?;
-
-        Ok(response.len())
+    pub fn new(reason: &TextComponent) -> Result<Self, ProtocolError> {
+        Ok(Self {
+            reason: ProtocolString::try_from(serde_json::to_string(reason)?)?,
+        })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Packet)]
+#[packet(id = 0x01, state = Login, direction = ClientBound)]
 pub struct EncryptionRequest {
-    server_id: String,
-    public_key_length: VarInt,
+    server_id: ProtocolString,
     public_key: Vec<u8>,
-    verify_token_length: VarInt,
     verify_token: Vec<u8>,
 }
 
-#[derive(Debug)]
+impl EncryptionRequest {
+    pub fn new(public_key: Vec<u8>, verify_token: Vec<u8>) -> Result<Self, ProtocolError> {
+        Ok(Self {
+            // has been empty ever since 1.7
+            server_id: ProtocolString::try_from("")?,
+            public_key,
+            verify_token,
+        })
+    }
+}
+
+#[derive(Debug, Packet)]
+#[packet(id = 0x02, state = Login, direction = ClientBound)]
 #[allow(clippy::module_name_repetitions)]
 pub struct LoginSuccess {
     pub uuid: Uuid,
     pub username: ProtocolString,
     pub number_of_properties: VarInt,
-    // the fields below are part of an "array" labelled property. i don't know
-    // how i'm supposed to represent the array in the stream yet, so i'll just
-    // leave it like this for now. hopefully before the next commit i can delete
-    // this comment
+    #[packet(length = number_of_properties)]
     property: Vec<Property>,
 }
 
+/// A profile property, like the `textures` that make skins and capes show up
 #[derive(Debug)]
-struct Property {
-    name: ProtocolString,
-    value: ProtocolString,
-    is_signed: bool,
-    signature: ProtocolString,
+pub struct Property {
+    pub name: ProtocolString,
+    pub value: ProtocolString,
+    pub is_signed: bool,
+    pub signature: Option<ProtocolString>,
 }
 
-impl LoginSuccess {
-    fn write_to<W>(&self, writer: &mut W) -> Result<usize, ProtocolError>
-    where
-        W: Write,
-    {
-        if !self.property.is_empty() {
-            trace!("unimplemented");
-            return Err(ProtocolError::Unimplemented);
+impl DataType for Property {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        let name = ProtocolString::read_from(reader)?;
+        let value = ProtocolString::read_from(reader)?;
+        let signature = Option::<ProtocolString>::read_from(reader)?;
+        let is_signed = signature.is_some();
+
+        Ok(Self {
+            name,
+            value,
+            is_signed,
+            signature,
+        })
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<usize, ProtocolError> {
+        // a signature that's there without the flag (or the other way around) would
+        // throw off every field after it
+        if self.is_signed != self.signature.is_some() {
+            return Err(ProtocolError::Malformed);
         }
 
-        let mut response = vec![];
-        let packet_id = VarInt(0x02);
-        let uuid = Uuid::as_bytes(&self.uuid);
-        let packet_length = VarInt(i32::try_from(
-            packet_id.size() + uuid.len() + self.username.size() + self.number_of_properties.size(),
-        )?);
+        let mut size = self.name.write_to(writer)?;
+        size += self.value.write_to(writer)?;
+        size += self.signature.write_to(writer)?;
 
-        packet_length.write_to(&mut response)?;
-        packet_id.write_to(&mut response)?;
-        response.extend_from_slice(uuid);
-        self.username.write_to(&mut response)?;
-        self.number_of_properties.write_to(&mut response)?;
+        Ok(size)
+    }
 
-        // TODO: write the rest. i'm still unsure as to how it works
+    fn size(&self) -> usize {
+        self.name.size() + self.value.size() + self.signature.size()
+    }
+}
 
-        writer.write_all(&response)?;
+impl TryFrom<ProfileProperty> for Property {
+    type Error = ProtocolError;
 
-        Ok(response.len())
+    fn try_from(value: ProfileProperty) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: ProtocolString::try_from(value.name)?,
+            value: ProtocolString::try_from(value.value)?,
+            is_signed: value.signature.is_some(),
+            signature: value.signature.map(ProtocolString::try_from).transpose()?,
+        })
     }
 }
 
-#[derive(Debug)]
+impl LoginSuccess {
+    pub fn new(
+        uuid: Uuid,
+        username: ProtocolString,
+        property: Vec<Property>,
+    ) -> Result<Self, ProtocolError> {
+        Ok(Self {
+            uuid,
+            username,
+            number_of_properties: VarInt::try_from(property.len())?,
+            property,
+        })
+    }
+}
+
+#[derive(Debug, Packet)]
+#[packet(id = 0x03, state = Login, direction = ClientBound)]
 pub struct SetCompression {
     threshold: VarInt,
 }
 
-#[derive(Debug)]
+impl SetCompression {
+    pub fn new(threshold: usize) -> Result<Self, ProtocolError> {
+        Ok(Self {
+            threshold: VarInt::try_from(threshold)?,
+        })
+    }
+}
+
+#[derive(Debug, Packet)]
+#[packet(id = 0x04, state = Login, direction = ClientBound)]
 pub struct PluginRequest {
     message_id: VarInt,
-    channel: ProtocolString,
-    data: Vec<u8>,
+    channel: Identifier,
+    // no length, the data just runs until the end of the packet
+    data: RemainingBytes,
+}
+
+impl PluginRequest {
+    pub const fn new(message_id: i32, channel: Identifier, data: Vec<u8>) -> Self {
+        Self {
+            message_id: VarInt(message_id),
+            channel,
+            data: RemainingBytes(data),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -174,88 +198,166 @@ pub enum ServerBound {
     LoginPluginResponse(LoginPluginResponse),
 }
 
-impl Decodable for ServerBound {
-    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
-        let length = VarInt::read_from(reader)?;
-        let length = usize::try_from(length.0)?;
-
-        let packet_id = VarInt::read_from(reader)?;
-        trace!("Login Packet ID: {:?}", packet_id);
-
-        let mut buffer = vec![0; length - packet_id.size()];
-        reader.read_exact(&mut buffer)?;
-        trace!("Buffer: {:?}", buffer);
-
-        let mut cursor = Cursor::new(&mut buffer);
-
-        match packet_id {
-            VarInt(0x00) => Ok(Self::LoginStart(LoginStart::read_from(&mut cursor)?)),
-            VarInt(0x01) => {
-                trace!("unimplemented");
-                Err(ProtocolError::Unimplemented)
-            }
-            VarInt(0x02) => {
-                trace!("unimplemented");
-                Err(ProtocolError::Unimplemented)
-            }
-
-            VarInt(n) => Err(ProtocolError::PacketId(n)),
-        }
-    }
-}
-
-#[derive(Debug)]
+#[derive(Debug, Packet)]
+#[packet(id = 0x00, state = Login, direction = ServerBound)]
 #[allow(clippy::module_name_repetitions)]
 pub struct LoginStart {
     pub name: ProtocolString,
     pub has_player_uuid: bool,
+    #[packet(optional = has_player_uuid)]
     pub player_uuid: Option<Uuid>,
 }
 
-impl LoginStart {
-    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
-        let name = ProtocolString::read_from(reader)?;
-        let has_player_uuid = reader.read_u8()? != 0;
-
-        let player_uuid = {
-            let mut rest_of_bytes = vec![];
-            match reader.read_to_end(&mut rest_of_bytes)? {
-                0 => None,
-                16 => {
-                    let buffer_for_uuid = rest_of_bytes[..16]
-                        .try_into()
-                        .map_err(|_| ProtocolError::Parsing)?;
-
-                    Some(Uuid::from_bytes(buffer_for_uuid))
-                }
-                _ => return Err(ProtocolError::Malformed),
-            }
-        };
+#[derive(Debug, Packet)]
+#[packet(id = 0x01, state = Login, direction = ServerBound)]
+pub struct EncryptionResponse {
+    pub shared_secret: Vec<u8>,
+    pub verify_token: Vec<u8>,
+}
 
-        if has_player_uuid && player_uuid.is_none() || !has_player_uuid && player_uuid.is_some() {
-            return Err(ProtocolError::Malformed);
+#[derive(Debug, Packet)]
+#[packet(id = 0x02, state = Login, direction = ServerBound)]
+#[allow(clippy::module_name_repetitions)]
+pub struct LoginPluginResponse {
+    pub message_id: VarInt,
+    /// `false` when the client didn't understand the channel
+    pub successful: bool,
+    #[packet(optional = successful)]
+    pub data: Option<RemainingBytes>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet;
+
+    /// Encode `packet`, decode it again and check that comes out as the same bytes
+    fn round_trip<P: Packet + Encodable + packet::Decodable>(packet: &P) -> (Vec<u8>, P) {
+        let mut bytes = vec![];
+        let size = packet.write_to(&mut bytes).unwrap();
+        assert_eq!(size, bytes.len());
+
+        let mut reader = &bytes[..];
+        assert_eq!(VarInt::read_from(&mut reader).unwrap(), VarInt(P::ID));
+        let decoded = packet::decode::<P, _>(&mut reader).unwrap();
+
+        let mut again = vec![];
+        decoded.write_to(&mut again).unwrap();
+        assert_eq!(again, bytes);
+
+        (bytes, decoded)
+    }
+
+    fn property(name: &str, signature: Option<&str>) -> Property {
+        ProfileProperty {
+            name: name.to_owned(),
+            value: "e30=".to_owned(),
+            signature: signature.map(str::to_owned),
         }
+        .try_into()
+        .unwrap()
+    }
 
-        Ok(Self {
-            name,
-            has_player_uuid,
-            player_uuid,
-        })
+    #[test]
+    fn encryption() {
+        let request = EncryptionRequest::new(vec![1, 2, 3], vec![9, 8, 7, 6]).unwrap();
+        let (bytes, _) = round_trip(&request);
+        assert_eq!(bytes, [0x01, 0x00, 0x03, 1, 2, 3, 0x04, 9, 8, 7, 6]);
+
+        // the response is laid out the same, minus the server id
+        let mut reader = &[0x02, 5, 5, 0x01, 7][..];
+        let response = packet::decode::<EncryptionResponse, _>(&mut reader).unwrap();
+        assert_eq!(response.shared_secret, [5, 5]);
+        assert_eq!(response.verify_token, [7]);
+
+        // lengths that run past the end, or stop short of it
+        for bytes in [&[0x03, 5, 5, 0x01, 7][..], &[0x01, 5, 5, 0x01, 7]] {
+            let mut reader = bytes;
+            assert!(packet::decode::<EncryptionResponse, _>(&mut reader).is_err());
+        }
     }
-}
 
-#[derive(Debug)]
-pub struct EncryptionResponse {
-    shared_secret_length: VarInt,
-    shared_secret: Vec<u8>,
-    verify_token_length: VarInt,
-    verify_token: Vec<u8>,
-}
+    #[test]
+    fn length() {
+        let success = LoginSuccess::new(
+            Uuid::nil(),
+            ProtocolString::try_from("Notch").unwrap(),
+            vec![property("textures", Some("c2ln")), property("other", None)],
+        )
+        .unwrap();
+
+        let (bytes, decoded) = round_trip(&success);
+        // the property count is only written once, by its own field
+        assert_eq!(bytes[1 + 16 + 6], 0x02);
+        assert_eq!(decoded.number_of_properties, VarInt(2));
+        assert_eq!(decoded.property.len(), 2);
+        assert_eq!(decoded.property[0].name.string, "textures");
+        assert!(decoded.property[0].is_signed);
+        assert!(!decoded.property[1].is_signed);
+
+        let empty = LoginSuccess::new(
+            Uuid::nil(),
+            ProtocolString::try_from("Notch").unwrap(),
+            vec![],
+        )
+        .unwrap();
+        let (bytes, _) = round_trip(&empty);
+        assert_eq!(bytes.last(), Some(&0x00));
+
+        // a count that doesn't match the properties can't be written
+        let lying = LoginSuccess {
+            number_of_properties: VarInt(3),
+            ..success
+        };
+        assert!(matches!(
+            lying.write_to(&mut vec![]),
+            Err(ProtocolError::Malformed)
+        ));
+    }
 
-#[derive(Debug)]
-#[allow(clippy::module_name_repetitions)]
-pub struct LoginPluginResponse {
-    message_id: VarInt,
-    successful: bool,
-    data: Option<Vec<u8>>,
+    #[test]
+    fn optional() {
+        let uuid = Uuid::parse_str("069a79f4-44e9-4726-a5be-fca90e38aaf5").unwrap();
+        let start = LoginStart {
+            name: ProtocolString::try_from("Notch").unwrap(),
+            has_player_uuid: true,
+            player_uuid: Some(uuid),
+        };
+        let (bytes, decoded) = round_trip(&start);
+        assert_eq!(bytes.len(), 1 + 6 + 1 + 16);
+        assert_eq!(decoded.player_uuid, Some(uuid));
+
+        let without = LoginStart {
+            name: ProtocolString::try_from("Notch").unwrap(),
+            has_player_uuid: false,
+            player_uuid: None,
+        };
+        let (bytes, decoded) = round_trip(&without);
+        assert_eq!(bytes, b"\x00\x05Notch\x00");
+        assert_eq!(decoded.player_uuid, None);
+
+        // the flag has to agree with the field
+        let lying = LoginStart {
+            has_player_uuid: false,
+            ..start
+        };
+        assert!(matches!(
+            lying.write_to(&mut vec![]),
+            Err(ProtocolError::Malformed)
+        ));
+
+        let response = LoginPluginResponse {
+            message_id: VarInt(7),
+            successful: true,
+            data: Some(RemainingBytes(vec![1, 2, 3])),
+        };
+        let (bytes, decoded) = round_trip(&response);
+        assert_eq!(bytes, [0x02, 0x07, 0x01, 1, 2, 3]);
+        assert_eq!(decoded.data, Some(RemainingBytes(vec![1, 2, 3])));
+
+        let mut reader = &[0x07, 0x00][..];
+        let decoded = packet::decode::<LoginPluginResponse, _>(&mut reader).unwrap();
+        assert!(!decoded.successful);
+        assert_eq!(decoded.data, None);
+    }
 }
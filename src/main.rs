@@ -1,31 +1,60 @@
+mod auth;
+mod chat;
 mod client;
+mod codec;
+mod command;
+mod config;
+mod crypto;
 mod data_types;
+mod favicon;
+mod forwarding;
 mod handshaking;
 mod login;
+mod nbt;
 mod packet;
 mod play;
+mod properties;
+mod proxy_protocol;
+mod query;
+mod rate_limit;
+mod rcon;
+mod registry;
+mod server;
 mod server_status;
 mod status;
 
-use std::{net::SocketAddr, ops::Index};
+use std::{collections::HashMap, net::SocketAddr, path::Path, sync::Arc};
 
 use thiserror::Error;
-use tokio::sync::mpsc::channel;
+use tokio::task::{self, JoinError, JoinSet};
 use tracing::{error, info, trace};
 
+/// The Minecraft version this server speaks
+pub const VERSION_NAME: &str = "1.20.1";
+
+/// The protocol number that goes with [`VERSION_NAME`]
+pub const PROTOCOL_VERSION: i32 = 763;
+
 #[derive(Debug, Error)]
 pub enum ProtocolError {
-    /// There's no packet id that matches the one given
-    #[error("Packet id doesn't have the type: {0}")]
-    PacketId(i32),
+    /// There's no packet with this id, in this state and direction
+    #[error("Unknown {direction:?} packet {id:#04x} in the {state:?} state")]
+    PacketId {
+        state: State,
+        direction: packet::Direction,
+        id: i32,
+    },
     /// Usually when parsing stuff, if there's a case of missing bytes, it should give back this error
     #[error("Missing data")]
     Missing,
     /// When the parsing simply fails or have unexpected value
     #[error("Malformed data")]
     Malformed,
+    /// A frame (or its length prefix) is bigger than what the protocol allows
+    #[error("Frame of {0} bytes is too large")]
+    FrameTooLarge(usize),
     #[error("IO error")]
-    /// Any error coming from std::io::Error
+    /// Any error coming from `std::io::Error`
     IOError(#[source] std::io::Error),
     /// For features that have not been implemented yet.
     #[error("Unimplemented")]
@@ -36,6 +65,32 @@ pub enum ProtocolError {
     SerdeJson(#[source] serde_json::error::Error),
     #[error("Internal error")]
     Internal,
+    /// Key exchange failed or the stream cipher couldn't be set up
+    #[error("Encryption error")]
+    Encryption,
+    /// A compressed packet lied about its size, or couldn't be inflated
+    #[error("Bad compressed packet")]
+    Compression,
+    /// A packet that's valid for the state, but not at this point of the conversation
+    #[error("Unexpected packet")]
+    UnexpectedPacket,
+    #[error("Session server error")]
+    SessionServer(#[source] reqwest::Error),
+    /// The PROXY protocol header is missing, malformed, or came from a peer we don't trust
+    #[error("PROXY protocol error")]
+    ProxyProtocol,
+    /// The client didn't answer in time
+    #[error("Timed out")]
+    Timeout,
+    /// The client should be kicked, with the reason being shown to them as is
+    #[error("{0}")]
+    Disconnect(String),
+    /// The server icon can't be used, with why
+    #[error("Invalid server icon: {0}")]
+    Favicon(String),
+    /// NBT that can't be read or written, or doesn't fit the type it's going to or from
+    #[error("Invalid NBT: {0}")]
+    Nbt(String),
     #[error("TryFromInt error")]
     TryFromInt(#[source] std::num::TryFromIntError),
 }
@@ -52,13 +107,19 @@ impl From<serde_json::error::Error> for ProtocolError {
     }
 }
 
+impl From<reqwest::Error> for ProtocolError {
+    fn from(error: reqwest::Error) -> Self {
+        Self::SessionServer(error)
+    }
+}
+
 impl From<std::num::TryFromIntError> for ProtocolError {
     fn from(error: std::num::TryFromIntError) -> Self {
         Self::TryFromInt(error)
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum State {
     Handshaking,
     Status,
@@ -87,36 +148,137 @@ async fn main() -> anyhow::Result<()> {
     //     // client.handle();
     // }
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:25565").await?;
-    let mut clients = vec![];
+    let config_path = Path::new(config::SERVER_PROPERTIES);
+    let config = config::ServerConfig::load(config_path)?;
+
+    let listener = tokio::net::TcpListener::bind(config.address).await?;
+    info!("Listening on ({})", config.address);
+
+    let server = Arc::new(server::Server::new(config)?);
+    let mut connections = JoinSet::new();
+    // which task is which client, for when a task dies without saying goodbye
+    let mut clients = HashMap::new();
     let (tx, mut rx) = tokio::sync::mpsc::channel::<SocketAddr>(32);
 
+    let host = listener.local_addr()?;
+    if let Some(port) = server.config().query_port {
+        let socket = tokio::net::UdpSocket::bind(SocketAddr::new(host.ip(), port)).await?;
+        connections.spawn(query::QueryServer::new(socket, Arc::clone(&server), host).run());
+    }
+
+    // loading the config already made sure there's a password
+    if let Some(port) = server.config().rcon_port {
+        let rcon = tokio::net::TcpListener::bind(SocketAddr::new(host.ip(), port)).await?;
+        let password = server.config().rcon_password.clone();
+        connections.spawn(rcon::RconServer::new(rcon, Arc::clone(&server), &password).run());
+    }
+
+    let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())?;
+
     loop {
         tokio::select! {
             res = listener.accept() => {
                 match res {
                     Ok((stream, addr)) => {
-                        clients.push(addr);
+                        info!("Client ({addr}) has connected.");
+                        server.register(addr);
+                        let server = Arc::clone(&server);
                         let tx = tx.clone();
-                        tokio::spawn(async move {
-                            info!("Client ({addr}) has connected.");
-                            client::Client::new(stream, addr, tx).handle().await;
-                        })
-                        .await?;
+                        let task = connections.spawn(async move {
+                            client::Client::new(stream, addr, server, tx).handle().await;
+                        });
+                        clients.insert(task.id(), addr);
                     },
                     Err(e) => {
                         error!("{e:?}");
-
                     }
                 }
             }
 
-            disconnect_addr = rx.recv() => {
-                let disconnect_addr = disconnect_addr.expect("recv fail");
-                info!("Client ({disconnect_addr}) has disconencted.");
-                clients.swap_remove(clients.iter().position(|a| *a == disconnect_addr).expect("addr should be inside, unless its disconnected"));
+            // main holds on to a sender, so the channel is never closed
+            Some(disconnect_addr) = rx.recv() => {
+                if let Some(client) = server.unregister(&disconnect_addr) {
+                    info!(
+                        "Client ({}) has disconnected after {:?}.",
+                        client.addr,
+                        client.connected_at.elapsed()
+                    );
+                }
+            }
+
+            Some(res) = connections.join_next_with_id() => {
+                task_finished(&server, &mut clients, res);
+            }
+
+            Some(()) = hangup.recv() => {
+                info!("Reloading {}.", config_path.display());
+                match config::ServerConfig::load(config_path) {
+                    Ok(config) => server.reload(config),
+                    Err(e) => error!("Keeping the old config: {e}"),
+                }
+            }
+
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutting down, closing {} connection(s).", connections.len());
+                connections.shutdown().await;
+                return Ok(());
             }
         }
-        trace!("List of clients: {clients:?}");
+        trace!("List of clients: {:?}", server.client_addrs());
+    }
+}
+
+/// Clean up after a connection task, which only unregisters its client by itself when it didn't panic
+fn task_finished(
+    server: &server::Server,
+    clients: &mut HashMap<task::Id, SocketAddr>,
+    result: Result<(task::Id, ()), JoinError>,
+) {
+    let id = match &result {
+        Ok((id, ())) => *id,
+        Err(e) => e.id(),
+    };
+    let addr = clients.remove(&id);
+
+    if let Err(e) = result {
+        error!("Connection task failed: {e:?}");
+
+        if let Some(client) = addr.and_then(|addr| server.unregister(&addr)) {
+            info!(
+                "Client ({}) is gone after {:?}, its task failed.",
+                client.addr,
+                client.connected_at.elapsed()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn failed_tasks_unregister_their_client() {
+        let server = server::Server::new(config::ServerConfig::default()).unwrap();
+        let mut connections = JoinSet::new();
+        let mut clients = HashMap::new();
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], 50000));
+        server.register(addr);
+        let task = connections.spawn(async { panic!("the client task broke") });
+        clients.insert(task.id(), addr);
+
+        // the others are left alone
+        let other = SocketAddr::from(([127, 0, 0, 1], 50001));
+        server.register(other);
+        let task = connections.spawn(async {});
+        clients.insert(task.id(), other);
+
+        while let Some(result) = connections.join_next_with_id().await {
+            task_finished(&server, &mut clients, result);
+        }
+
+        assert!(clients.is_empty());
+        assert_eq!(server.client_addrs(), [other]);
     }
 }
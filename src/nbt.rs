@@ -0,0 +1,921 @@
+//! Named Binary Tag, the format of chunks, items, registries and `level.dat`
+//!
+//! Everything is big endian, strings are Java's modified UTF-8 with a `u16`
+//! length, and a document is one compound with a name in front. Files on disk
+//! are usually gzipped (sometimes zlib'd) on top of it all.
+//!
+//! Packets in the protocol this server speaks (763) keep the name too, so
+//! [`Nbt`] is what goes in them as well. [`NetworkNbt`] is the nameless root
+//! that later protocol versions (764 and up) switched to, nothing uses it yet.
+//!
+//! [`Tag`] is the whole model. Typed structs go to and from it through serde
+//! with [`ser::to_compound`] and [`de::from_compound`], and text through
+//! `FromStr` and `Display` as SNBT.
+
+pub mod de;
+pub mod ser;
+mod snbt;
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::{GzEncoder, ZlibEncoder},
+};
+
+use crate::{
+    data_types::{DataType, MAX_PREALLOCATED},
+    ProtocolError,
+};
+
+/// How deep lists and compounds can nest, same as vanilla
+const MAX_DEPTH: usize = 512;
+
+/// How much memory NBT from a packet can take up, same as vanilla
+const NETWORK_BUDGET: usize = 2 * 1024 * 1024;
+
+pub const TAG_END: u8 = 0;
+pub const TAG_BYTE: u8 = 1;
+pub const TAG_SHORT: u8 = 2;
+pub const TAG_INT: u8 = 3;
+pub const TAG_LONG: u8 = 4;
+pub const TAG_FLOAT: u8 = 5;
+pub const TAG_DOUBLE: u8 = 6;
+pub const TAG_BYTE_ARRAY: u8 = 7;
+pub const TAG_STRING: u8 = 8;
+pub const TAG_LIST: u8 = 9;
+pub const TAG_COMPOUND: u8 = 10;
+pub const TAG_INT_ARRAY: u8 = 11;
+pub const TAG_LONG_ARRAY: u8 = 12;
+
+/// Any NBT value, `TAG_End` only exists on the wire so it has no variant
+// nothing reads or sends NBT before play, which isn't here yet
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    /// Every element has to be the same kind of tag
+    List(Vec<Self>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+#[allow(dead_code)]
+impl Tag {
+    pub const fn id(&self) -> u8 {
+        match self {
+            Self::Byte(_) => TAG_BYTE,
+            Self::Short(_) => TAG_SHORT,
+            Self::Int(_) => TAG_INT,
+            Self::Long(_) => TAG_LONG,
+            Self::Float(_) => TAG_FLOAT,
+            Self::Double(_) => TAG_DOUBLE,
+            Self::ByteArray(_) => TAG_BYTE_ARRAY,
+            Self::String(_) => TAG_STRING,
+            Self::List(_) => TAG_LIST,
+            Self::Compound(_) => TAG_COMPOUND,
+            Self::IntArray(_) => TAG_INT_ARRAY,
+            Self::LongArray(_) => TAG_LONG_ARRAY,
+        }
+    }
+
+    /// Read the payload of a tag whose id was already read
+    fn read_payload<R: Read>(
+        id: u8,
+        reader: &mut R,
+        depth: usize,
+        budget: &mut Budget,
+    ) -> Result<Self, ProtocolError> {
+        if depth > MAX_DEPTH {
+            return Err(ProtocolError::Nbt(format!(
+                "nested deeper than {MAX_DEPTH} levels"
+            )));
+        }
+
+        // a tag can be a single byte on the wire, so this is what keeps a long
+        // list of empty compounds from turning into far more memory than bytes
+        budget.take(std::mem::size_of::<Self>())?;
+
+        let tag = match id {
+            TAG_BYTE => Self::Byte(reader.read_i8()?),
+            TAG_SHORT => Self::Short(reader.read_i16::<BigEndian>()?),
+            TAG_INT => Self::Int(reader.read_i32::<BigEndian>()?),
+            TAG_LONG => Self::Long(reader.read_i64::<BigEndian>()?),
+            TAG_FLOAT => Self::Float(reader.read_f32::<BigEndian>()?),
+            TAG_DOUBLE => Self::Double(reader.read_f64::<BigEndian>()?),
+            TAG_BYTE_ARRAY => Self::ByteArray(read_array(reader, budget, ReadBytesExt::read_i8)?),
+            TAG_STRING => Self::String(read_string(reader, budget)?),
+            TAG_LIST => {
+                let element_id = reader.read_u8()?;
+                let length = read_length(reader)?;
+
+                if element_id == TAG_END && length > 0 {
+                    return Err(ProtocolError::Nbt("list of TAG_End".to_owned()));
+                }
+
+                let mut elements = Vec::with_capacity(length.min(MAX_PREALLOCATED));
+                for _ in 0..length {
+                    elements.push(Self::read_payload(element_id, reader, depth + 1, budget)?);
+                }
+                Self::List(elements)
+            }
+            TAG_COMPOUND => Self::Compound(Compound::read_payload(reader, depth + 1, budget)?),
+            TAG_INT_ARRAY => Self::IntArray(read_array(
+                reader,
+                budget,
+                ReadBytesExt::read_i32::<BigEndian>,
+            )?),
+            TAG_LONG_ARRAY => Self::LongArray(read_array(
+                reader,
+                budget,
+                ReadBytesExt::read_i64::<BigEndian>,
+            )?),
+            id => return Err(ProtocolError::Nbt(format!("unknown tag id {id}"))),
+        };
+
+        Ok(tag)
+    }
+
+    fn write_payload<W: Write>(&self, writer: &mut W) -> Result<usize, ProtocolError> {
+        let size = match self {
+            Self::Byte(value) => {
+                writer.write_i8(*value)?;
+                1
+            }
+            Self::Short(value) => {
+                writer.write_i16::<BigEndian>(*value)?;
+                2
+            }
+            Self::Int(value) => {
+                writer.write_i32::<BigEndian>(*value)?;
+                4
+            }
+            Self::Long(value) => {
+                writer.write_i64::<BigEndian>(*value)?;
+                8
+            }
+            Self::Float(value) => {
+                writer.write_f32::<BigEndian>(*value)?;
+                4
+            }
+            Self::Double(value) => {
+                writer.write_f64::<BigEndian>(*value)?;
+                8
+            }
+            Self::ByteArray(values) => {
+                write_length(writer, values.len())?;
+                for value in values {
+                    writer.write_i8(*value)?;
+                }
+                4 + values.len()
+            }
+            Self::String(value) => write_string(writer, value)?,
+            Self::List(elements) => {
+                let element_id = elements.first().map_or(TAG_END, Self::id);
+                if elements.iter().any(|element| element.id() != element_id) {
+                    return Err(ProtocolError::Nbt(
+                        "list with more than one kind of tag".to_owned(),
+                    ));
+                }
+
+                writer.write_u8(element_id)?;
+                write_length(writer, elements.len())?;
+
+                let mut size = 5;
+                for element in elements {
+                    size += element.write_payload(writer)?;
+                }
+                size
+            }
+            Self::Compound(compound) => compound.write_payload(writer)?,
+            Self::IntArray(values) => {
+                write_length(writer, values.len())?;
+                for value in values {
+                    writer.write_i32::<BigEndian>(*value)?;
+                }
+                4 + values.len() * 4
+            }
+            Self::LongArray(values) => {
+                write_length(writer, values.len())?;
+                for value in values {
+                    writer.write_i64::<BigEndian>(*value)?;
+                }
+                4 + values.len() * 8
+            }
+        };
+
+        Ok(size)
+    }
+}
+
+macro_rules! tag_from {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$ty> for Tag {
+                fn from(value: $ty) -> Self {
+                    Self::$variant(value.into())
+                }
+            }
+        )*
+    };
+}
+
+tag_from! {
+    i8 => Byte,
+    i16 => Short,
+    i32 => Int,
+    i64 => Long,
+    f32 => Float,
+    f64 => Double,
+    &str => String,
+    String => String,
+    Compound => Compound,
+}
+
+/// Named tags, which keep the order they were added in so writing back doesn't shuffle them
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Compound {
+    entries: Vec<(String, Tag)>,
+}
+
+#[allow(dead_code)]
+impl Compound {
+    pub const fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        self.entries
+            .iter()
+            .find(|(entry, _)| entry == name)
+            .map(|(_, tag)| tag)
+    }
+
+    /// Replace the tag called `name`, or add it at the end if it's new
+    pub fn insert(&mut self, name: impl Into<String>, tag: impl Into<Tag>) {
+        let name = name.into();
+        let tag = tag.into();
+
+        if let Some((_, existing)) = self.entries.iter_mut().find(|(entry, _)| *entry == name) {
+            *existing = tag;
+        } else {
+            self.entries.push((name, tag));
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Tag> {
+        let index = self.entries.iter().position(|(entry, _)| entry == name)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Tag)> {
+        self.entries.iter().map(|(name, tag)| (name.as_str(), tag))
+    }
+
+    pub const fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn read_payload<R: Read>(
+        reader: &mut R,
+        depth: usize,
+        budget: &mut Budget,
+    ) -> Result<Self, ProtocolError> {
+        let mut compound = CompoundBuilder::default();
+
+        loop {
+            let id = reader.read_u8()?;
+            if id == TAG_END {
+                return Ok(compound.finish());
+            }
+
+            budget.take(std::mem::size_of::<(String, Tag)>() - std::mem::size_of::<Tag>())?;
+            let name = read_string(reader, budget)?;
+            let tag = Tag::read_payload(id, reader, depth, budget)?;
+            compound.insert(name, tag);
+        }
+    }
+
+    fn write_payload<W: Write>(&self, writer: &mut W) -> Result<usize, ProtocolError> {
+        let mut size = 1;
+
+        for (name, tag) in &self.entries {
+            writer.write_u8(tag.id())?;
+            size += 1 + write_string(writer, name)?;
+            size += tag.write_payload(writer)?;
+        }
+        writer.write_u8(TAG_END)?;
+
+        Ok(size)
+    }
+}
+
+impl<K: Into<String>> FromIterator<(K, Tag)> for Compound {
+    fn from_iter<I: IntoIterator<Item = (K, Tag)>>(iter: I) -> Self {
+        let mut compound = CompoundBuilder::default();
+        for (name, tag) in iter {
+            compound.insert(name.into(), tag);
+        }
+        compound.finish()
+    }
+}
+
+impl IntoIterator for Compound {
+    type Item = (String, Tag);
+    type IntoIter = std::vec::IntoIter<(String, Tag)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// Builds a compound out of a lot of tags at once, which `Compound::insert` would have
+/// to compare every name against all the ones before it for
+#[derive(Debug, Default)]
+struct CompoundBuilder {
+    entries: Vec<(String, Tag)>,
+    positions: HashMap<String, usize>,
+}
+
+impl CompoundBuilder {
+    /// Same as `Compound::insert`, a repeated name replaces the tag where it first was
+    fn insert(&mut self, name: String, tag: Tag) {
+        if let Some(&position) = self.positions.get(&name) {
+            self.entries[position].1 = tag;
+        } else {
+            self.positions.insert(name.clone(), self.entries.len());
+            self.entries.push((name, tag));
+        }
+    }
+
+    fn finish(self) -> Compound {
+        Compound {
+            entries: self.entries,
+        }
+    }
+}
+
+/// Roughly how much memory a read is still allowed to take, like vanilla's `NbtAccounter`
+#[derive(Debug)]
+struct Budget {
+    limit: usize,
+    remaining: usize,
+}
+
+impl Budget {
+    const fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            remaining: limit,
+        }
+    }
+
+    /// Files are trusted as much as vanilla trusts them, which is completely
+    const fn unlimited() -> Self {
+        Self::new(usize::MAX)
+    }
+
+    fn take(&mut self, bytes: usize) -> Result<(), ProtocolError> {
+        self.remaining = self.remaining.checked_sub(bytes).ok_or_else(|| {
+            ProtocolError::Nbt(format!("takes up more than {} bytes", self.limit))
+        })?;
+
+        Ok(())
+    }
+}
+
+/// What a file is wrapped in, vanilla gzips nearly everything it writes
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zlib,
+}
+
+#[allow(dead_code)]
+impl Compression {
+    /// Tell from the first bytes, uncompressed NBT always starts with `TAG_Compound`
+    pub fn detect(bytes: &[u8]) -> Self {
+        match bytes {
+            [0x1F, 0x8B, ..] => Self::Gzip,
+            [0x78, ..] => Self::Zlib,
+            _ => Self::None,
+        }
+    }
+}
+
+/// A whole document, the root compound with its name, the way files store it
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Nbt {
+    pub name: String,
+    pub root: Compound,
+}
+
+#[allow(dead_code)]
+impl Nbt {
+    pub fn new(name: impl Into<String>, root: Compound) -> Self {
+        Self {
+            name: name.into(),
+            root,
+        }
+    }
+
+    /// Read a file's worth of NBT, compressed or not
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ProtocolError> {
+        let budget = &mut Budget::unlimited();
+
+        match Compression::detect(bytes) {
+            Compression::None => Self::read_with(&mut &bytes[..], budget),
+            Compression::Gzip => Self::read_with(&mut GzDecoder::new(bytes), budget),
+            Compression::Zlib => Self::read_with(&mut ZlibDecoder::new(bytes), budget),
+        }
+    }
+
+    fn read_with<R: Read>(reader: &mut R, budget: &mut Budget) -> Result<Self, ProtocolError> {
+        read_root_id(reader)?;
+        let name = read_string(reader, budget)?;
+        let root = Compound::read_payload(reader, 0, budget)?;
+
+        Ok(Self { name, root })
+    }
+
+    pub fn to_bytes(&self, compression: Compression) -> Result<Vec<u8>, ProtocolError> {
+        match compression {
+            Compression::None => {
+                let mut bytes = vec![];
+                self.write_to(&mut bytes)?;
+                Ok(bytes)
+            }
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+                self.write_to(&mut encoder)?;
+                Ok(encoder.finish()?)
+            }
+            Compression::Zlib => {
+                let mut encoder = ZlibEncoder::new(vec![], flate2::Compression::default());
+                self.write_to(&mut encoder)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+}
+
+impl DataType for Nbt {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        Self::read_with(reader, &mut Budget::new(NETWORK_BUDGET))
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<usize, ProtocolError> {
+        writer.write_u8(TAG_COMPOUND)?;
+        let size = write_string(writer, &self.name)?;
+
+        Ok(1 + size + self.root.write_payload(writer)?)
+    }
+
+    fn size(&self) -> usize {
+        self.write_to(&mut std::io::sink()).unwrap_or(0)
+    }
+}
+
+/// The root compound without a name, for protocol 764 and up, not the 763 this server speaks
+// only there for when the protocol version moves past 1.20.1
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkNbt(pub Compound);
+
+impl DataType for NetworkNbt {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        read_root_id(reader)?;
+        let budget = &mut Budget::new(NETWORK_BUDGET);
+
+        Ok(Self(Compound::read_payload(reader, 0, budget)?))
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<usize, ProtocolError> {
+        writer.write_u8(TAG_COMPOUND)?;
+
+        Ok(1 + self.0.write_payload(writer)?)
+    }
+
+    fn size(&self) -> usize {
+        self.write_to(&mut std::io::sink()).unwrap_or(0)
+    }
+}
+
+fn read_root_id<R: Read>(reader: &mut R) -> Result<(), ProtocolError> {
+    match reader.read_u8()? {
+        TAG_COMPOUND => Ok(()),
+        id => Err(ProtocolError::Nbt(format!(
+            "the root has to be a compound, not tag {id}"
+        ))),
+    }
+}
+
+fn read_length<R: Read>(reader: &mut R) -> Result<usize, ProtocolError> {
+    let length = reader.read_i32::<BigEndian>()?;
+
+    usize::try_from(length).map_err(|_| ProtocolError::Nbt(format!("negative length {length}")))
+}
+
+fn write_length<W: Write>(writer: &mut W, length: usize) -> Result<(), ProtocolError> {
+    writer.write_i32::<BigEndian>(i32::try_from(length)?)?;
+    Ok(())
+}
+
+fn read_array<R, T, F>(
+    reader: &mut R,
+    budget: &mut Budget,
+    read: F,
+) -> Result<Vec<T>, ProtocolError>
+where
+    R: Read,
+    F: Fn(&mut R) -> std::io::Result<T>,
+{
+    let length = read_length(reader)?;
+    budget.take(length.saturating_mul(std::mem::size_of::<T>()))?;
+
+    let mut values = Vec::with_capacity(length.min(MAX_PREALLOCATED));
+    for _ in 0..length {
+        values.push(read(reader)?);
+    }
+
+    Ok(values)
+}
+
+fn read_string<R: Read>(reader: &mut R, budget: &mut Budget) -> Result<String, ProtocolError> {
+    let length = usize::from(reader.read_u16::<BigEndian>()?);
+    budget.take(length)?;
+
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+
+    from_modified_utf8(&bytes)
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> Result<usize, ProtocolError> {
+    let bytes = to_modified_utf8(string);
+    let length = u16::try_from(bytes.len()).map_err(|_| {
+        ProtocolError::Nbt(format!(
+            "string of {} bytes doesn't fit a u16 length",
+            bytes.len()
+        ))
+    })?;
+
+    writer.write_u16::<BigEndian>(length)?;
+    writer.write_all(&bytes)?;
+
+    Ok(2 + bytes.len())
+}
+
+/// Java's take on UTF-8, which encodes UTF-16 units instead of characters, so
+/// anything past the BMP becomes two 3 byte surrogates, and a null is `C0 80`
+#[allow(clippy::cast_possible_truncation)]
+fn to_modified_utf8(string: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(string.len());
+
+    // every cast is of something already masked or shifted down to fit a byte
+    for unit in string.encode_utf16() {
+        match unit {
+            0x01..=0x7F => bytes.push(unit as u8),
+            0x00 | 0x80..=0x7FF => {
+                bytes.extend_from_slice(&[0xC0 | (unit >> 6) as u8, 0x80 | (unit & 0x3F) as u8]);
+            }
+            _ => bytes.extend_from_slice(&[
+                0xE0 | (unit >> 12) as u8,
+                0x80 | ((unit >> 6) & 0x3F) as u8,
+                0x80 | (unit & 0x3F) as u8,
+            ]),
+        }
+    }
+
+    bytes
+}
+
+fn from_modified_utf8(bytes: &[u8]) -> Result<String, ProtocolError> {
+    let invalid = || ProtocolError::Nbt("invalid modified UTF-8".to_owned());
+    let continuation = |byte: Option<&u8>| match byte {
+        Some(byte) if byte & 0xC0 == 0x80 => Ok(u16::from(byte & 0x3F)),
+        _ => Err(invalid()),
+    };
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+
+    while let Some(&byte) = iter.next() {
+        let unit = match byte {
+            // Java never writes a plain null, but it does read one
+            0x00..=0x7F => u16::from(byte),
+            0xC0..=0xDF => (u16::from(byte & 0x1F) << 6) | continuation(iter.next())?,
+            0xE0..=0xEF => {
+                (u16::from(byte & 0x0F) << 12)
+                    | (continuation(iter.next())? << 6)
+                    | continuation(iter.next())?
+            }
+            _ => return Err(invalid()),
+        };
+        units.push(unit);
+    }
+
+    String::from_utf16(&units).map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    /// A gzipped `level.dat` laid out the way 1.20.1 writes one, cut down to
+    /// a few fields of each kind
+    const LEVEL_DAT: &[u8] = include_bytes!("nbt/fixtures/level.dat");
+
+    /// A root compound holding one list of `count` empty compounds
+    fn empty_compounds(count: i32) -> Vec<u8> {
+        let mut bytes = vec![TAG_COMPOUND, TAG_LIST, 0, 1, b'l', TAG_COMPOUND];
+        bytes.extend_from_slice(&count.to_be_bytes());
+        bytes.resize(bytes.len() + usize::try_from(count).unwrap(), TAG_END);
+        bytes.push(TAG_END);
+        bytes
+    }
+
+    #[test]
+    fn network_reads_have_a_budget() {
+        let small = empty_compounds(1000);
+        let NetworkNbt(root) = NetworkNbt::read_from(&mut &small[..]).unwrap();
+        assert!(matches!(root.get("l"), Some(Tag::List(list)) if list.len() == 1000));
+
+        // a few hundred kilobytes on the wire, many megabytes once read
+        let large = empty_compounds(300_000);
+        assert!(matches!(
+            NetworkNbt::read_from(&mut &large[..]),
+            Err(ProtocolError::Nbt(_))
+        ));
+
+        // huge arrays are turned down before anything gets allocated for them
+        let mut array = vec![TAG_COMPOUND, TAG_LONG_ARRAY, 0, 0];
+        array.extend_from_slice(&i32::MAX.to_be_bytes());
+        assert!(matches!(
+            NetworkNbt::read_from(&mut &array[..]),
+            Err(ProtocolError::Nbt(_))
+        ));
+    }
+
+    #[test]
+    fn files_have_no_budget() {
+        // a file is the same bytes with an empty name in front
+        let mut bytes = empty_compounds(300_000);
+        bytes.splice(1..1, [0, 0]);
+
+        let nbt = Nbt::from_bytes(&bytes).unwrap();
+        assert!(matches!(nbt.root.get("l"), Some(Tag::List(list)) if list.len() == 300_000));
+    }
+
+    #[test]
+    fn repeated_names_keep_the_last_tag() {
+        let mut bytes = vec![TAG_COMPOUND];
+        for (name, value) in [(b'a', 1), (b'b', 2), (b'a', 3)] {
+            bytes.extend_from_slice(&[TAG_BYTE, 0, 1, name, value]);
+        }
+        bytes.push(TAG_END);
+
+        let NetworkNbt(root) = NetworkNbt::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(
+            root,
+            Compound::from_iter([("a", Tag::Byte(3)), ("b", Tag::Byte(2))])
+        );
+    }
+
+    #[test]
+    fn hello_world() {
+        // the smallest example from the original NBT spec
+        let bytes = b"\x0a\x00\x0bhello world\x08\x00\x04name\x00\x09Bananrama\x00";
+
+        let nbt = Nbt::from_bytes(bytes).unwrap();
+        assert_eq!(nbt.name, "hello world");
+        assert_eq!(nbt.root.get("name"), Some(&Tag::from("Bananrama")));
+        assert_eq!(nbt.to_bytes(Compression::None).unwrap(), bytes);
+    }
+
+    #[test]
+    fn level_dat_round_trip() {
+        let mut raw = vec![];
+        GzDecoder::new(LEVEL_DAT).read_to_end(&mut raw).unwrap();
+
+        let nbt = Nbt::from_bytes(LEVEL_DAT).unwrap();
+        assert_eq!(nbt.name, "");
+
+        let Some(Tag::Compound(data)) = nbt.root.get("Data") else {
+            panic!("no Data compound");
+        };
+        assert_eq!(data.get("LevelName"), Some(&Tag::from("New World")));
+        assert_eq!(data.get("DataVersion"), Some(&Tag::Int(3465)));
+        assert_eq!(data.get("LastPlayed"), Some(&Tag::Long(1_696_161_357_283)));
+        assert_eq!(data.get("BorderSize"), Some(&Tag::Double(5.999_996_8e7)));
+
+        let Some(Tag::Compound(player)) = data.get("Player") else {
+            panic!("no Player compound");
+        };
+        assert_eq!(
+            player.get("UUID"),
+            Some(&Tag::IntArray(vec![
+                -1_165_394_270,
+                -1_376_369_549,
+                -1_561_648_738,
+                1_311_406_227
+            ]))
+        );
+        assert_eq!(player.get("Fire"), Some(&Tag::Short(-20)));
+        assert_eq!(player.get("Health"), Some(&Tag::Float(20.0)));
+        assert_eq!(player.get("Inventory"), Some(&Tag::List(vec![])));
+
+        // byte for byte what was read, order and all
+        assert_eq!(nbt.to_bytes(Compression::None).unwrap(), raw);
+
+        for compression in [Compression::Gzip, Compression::Zlib] {
+            let bytes = nbt.to_bytes(compression).unwrap();
+            assert_eq!(Compression::detect(&bytes), compression);
+            assert_eq!(Nbt::from_bytes(&bytes).unwrap(), nbt);
+        }
+    }
+
+    #[test]
+    fn network_nbt_has_no_name() {
+        let root = Compound::from_iter([("a", Tag::Byte(1))]);
+        let mut bytes = vec![];
+        NetworkNbt(root.clone()).write_to(&mut bytes).unwrap();
+
+        assert_eq!(bytes, [TAG_COMPOUND, TAG_BYTE, 0, 1, b'a', 1, TAG_END]);
+        assert_eq!(
+            NetworkNbt::read_from(&mut &bytes[..]).unwrap(),
+            NetworkNbt(root)
+        );
+
+        let not_a_compound = [TAG_LIST, TAG_END, 0, 0, 0, 0];
+        assert!(matches!(
+            NetworkNbt::read_from(&mut &not_a_compound[..]),
+            Err(ProtocolError::Nbt(_))
+        ));
+    }
+
+    #[test]
+    fn modified_utf8() {
+        let cases: [(&str, &[u8]); 4] = [
+            ("copper", b"copper"),
+            ("\u{e9}", &[0xC3, 0xA9]),
+            // a null is two bytes so there's never a zero byte in a string
+            ("a\0b", &[b'a', 0xC0, 0x80, b'b']),
+            // and anything past the BMP is a surrogate pair, three bytes each
+            ("\u{1F600}", &[0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80]),
+        ];
+
+        for (string, encoded) in cases {
+            let mut bytes = vec![];
+            assert_eq!(write_string(&mut bytes, string).unwrap(), 2 + encoded.len());
+            assert_eq!(
+                &bytes[..2],
+                u16::try_from(encoded.len()).unwrap().to_be_bytes()
+            );
+            assert_eq!(&bytes[2..], encoded);
+
+            let budget = &mut Budget::unlimited();
+            assert_eq!(read_string(&mut &bytes[..], budget).unwrap(), string);
+        }
+
+        // Java reads a plain null too
+        assert_eq!(from_modified_utf8(&[b'a', 0, b'b']).unwrap(), "a\0b");
+
+        for invalid in [
+            // regular UTF-8 for a character past the BMP
+            &[0xF0, 0x9F, 0x98, 0x80][..],
+            // a lone surrogate
+            &[0xED, 0xA0, 0xBD],
+            // cut off
+            &[0xC3],
+            &[0x80],
+        ] {
+            assert!(matches!(
+                from_modified_utf8(invalid),
+                Err(ProtocolError::Nbt(_))
+            ));
+        }
+    }
+
+    /// A root compound holding `depth` lists, each the only element of the one before
+    fn nested_lists(depth: usize) -> Vec<u8> {
+        let mut bytes = vec![TAG_COMPOUND, TAG_LIST, 0, 1, b'l'];
+        for _ in 1..depth {
+            bytes.extend_from_slice(&[TAG_LIST, 0, 0, 0, 1]);
+        }
+        bytes.extend_from_slice(&[TAG_END, 0, 0, 0, 0, TAG_END]);
+        bytes
+    }
+
+    #[test]
+    fn depth_limit() {
+        // the outermost list is the first level, same as the SNBT parser counts
+        let deepest = nested_lists(MAX_DEPTH + 1);
+        assert!(NetworkNbt::read_from(&mut &deepest[..]).is_ok());
+
+        let too_deep = nested_lists(MAX_DEPTH + 2);
+        assert!(matches!(
+            NetworkNbt::read_from(&mut &too_deep[..]),
+            Err(ProtocolError::Nbt(_))
+        ));
+
+        // files have no budget, but the depth is still limited
+        let mut file = too_deep;
+        file.splice(1..1, [0, 0]);
+        assert!(matches!(Nbt::from_bytes(&file), Err(ProtocolError::Nbt(_))));
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Chunk {
+        x_pos: i32,
+        z_pos: i32,
+        status: String,
+        is_light_on: bool,
+        inhabited_time: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        last_update: Option<i64>,
+        #[serde(with = "ser::byte_array")]
+        biomes: Vec<i8>,
+        #[serde(with = "ser::int_array")]
+        owner: Vec<i32>,
+        #[serde(with = "ser::long_array")]
+        heightmap: Vec<i64>,
+        sections: Vec<Section>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Section {
+        #[serde(rename = "Y")]
+        y: i8,
+        palette: Vec<String>,
+        sky_light: f32,
+    }
+
+    #[test]
+    fn typed_struct_round_trip() {
+        let chunk = Chunk {
+            x_pos: -3,
+            z_pos: 12,
+            status: "minecraft:full".to_owned(),
+            is_light_on: true,
+            inhabited_time: 1 << 40,
+            last_update: None,
+            biomes: vec![-1, 0, 127],
+            owner: vec![-1_165_394_270, 1, 2, 3],
+            heightmap: vec![i64::MIN, 0, i64::MAX],
+            sections: vec![Section {
+                y: -4,
+                palette: vec!["minecraft:stone".to_owned(), "minecraft:air".to_owned()],
+                sky_light: 0.5,
+            }],
+        };
+
+        let compound = ser::to_compound(&chunk).unwrap();
+        assert_eq!(compound.get("IsLightOn"), Some(&Tag::Byte(1)));
+        assert_eq!(compound.get("LastUpdate"), None);
+        assert_eq!(
+            compound.get("Biomes"),
+            Some(&Tag::ByteArray(vec![-1, 0, 127]))
+        );
+        assert!(matches!(compound.get("Owner"), Some(Tag::IntArray(owner)) if owner.len() == 4));
+        assert_eq!(
+            compound.get("Heightmap"),
+            Some(&Tag::LongArray(vec![i64::MIN, 0, i64::MAX]))
+        );
+        assert!(matches!(
+            compound.get("Sections"),
+            Some(Tag::List(sections)) if matches!(sections[..], [Tag::Compound(_)])
+        ));
+
+        // through the wire and back, which keeps the array tags as arrays
+        let mut bytes = vec![];
+        NetworkNbt(compound.clone()).write_to(&mut bytes).unwrap();
+        let NetworkNbt(read) = NetworkNbt::read_from(&mut &bytes[..]).unwrap();
+        assert_eq!(read, compound);
+
+        assert_eq!(de::from_compound::<Chunk>(read).unwrap(), chunk);
+    }
+}
@@ -0,0 +1,393 @@
+//! [`Tag`]s into Rust values
+//!
+//! The other way around from `ser`, a bit more forgiving: any integer tag can
+//! go into any integer type it fits, and the array tags can be read as plain
+//! sequences. Deserializing a [`Tag`] itself keeps the array tags intact.
+use std::fmt;
+
+use serde::{
+    de::{
+        self,
+        value::{SeqDeserializer, StringDeserializer},
+        DeserializeOwned, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess,
+        Visitor,
+    },
+    forward_to_deserialize_any, Deserialize,
+};
+
+use super::{
+    ser::{BYTE_ARRAY, INT_ARRAY, LONG_ARRAY},
+    Compound, CompoundBuilder, Tag,
+};
+use crate::ProtocolError;
+
+/// The newtype name `Tag` asks for itself with, so the array tags can come through as they are
+const TAG: &str = "__nbt_tag";
+
+#[allow(dead_code)]
+pub fn from_tag<T: DeserializeOwned>(tag: Tag) -> Result<T, ProtocolError> {
+    T::deserialize(Deserializer { tag })
+}
+
+#[allow(dead_code)]
+pub fn from_compound<T: DeserializeOwned>(compound: Compound) -> Result<T, ProtocolError> {
+    from_tag(Tag::Compound(compound))
+}
+
+pub struct Deserializer {
+    tag: Tag,
+}
+
+impl IntoDeserializer<'_, ProtocolError> for Tag {
+    type Deserializer = Deserializer;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        Deserializer { tag: self }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = ProtocolError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.tag {
+            Tag::Byte(value) => visitor.visit_i8(value),
+            Tag::Short(value) => visitor.visit_i16(value),
+            Tag::Int(value) => visitor.visit_i32(value),
+            Tag::Long(value) => visitor.visit_i64(value),
+            Tag::Float(value) => visitor.visit_f32(value),
+            Tag::Double(value) => visitor.visit_f64(value),
+            Tag::ByteArray(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Tag::String(value) => visitor.visit_string(value),
+            Tag::List(elements) => visitor.visit_seq(SeqDeserializer::new(elements.into_iter())),
+            Tag::Compound(compound) => visitor.visit_map(CompoundAccess::new(compound)),
+            Tag::IntArray(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+            Tag::LongArray(values) => visitor.visit_seq(SeqDeserializer::new(values.into_iter())),
+        }
+    }
+
+    /// There's no boolean tag, vanilla uses a byte that's 0 or 1
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.tag {
+            Tag::Byte(value) => visitor.visit_bool(value != 0),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.tag {
+            Tag::ByteArray(values) => {
+                visitor.visit_byte_buf(values.into_iter().map(|v| v.to_be_bytes()[0]).collect())
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    /// Only here when it's there, missing fields are `None` without asking
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        // the array tags turn into a map with the kind of array as the key,
+        // which only `TagVisitor` ever asks for
+        let (kind, values): (_, Vec<Tag>) = match (name, self.tag) {
+            (TAG, Tag::ByteArray(values)) => {
+                (BYTE_ARRAY, values.into_iter().map(Tag::Byte).collect())
+            }
+            (TAG, Tag::IntArray(values)) => (INT_ARRAY, values.into_iter().map(Tag::Int).collect()),
+            (TAG, Tag::LongArray(values)) => {
+                (LONG_ARRAY, values.into_iter().map(Tag::Long).collect())
+            }
+            (TAG, tag) => return Self { tag }.deserialize_any(visitor),
+            (_, tag) => return visitor.visit_newtype_struct(Self { tag }),
+        };
+
+        let mut array = Compound::new();
+        array.insert(kind, Tag::List(values));
+        visitor.visit_map(CompoundAccess::new(array))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.tag {
+            Tag::String(variant) => visitor.visit_enum(StringDeserializer::new(variant)),
+            Tag::Compound(compound) if compound.len() == 1 => {
+                let Some((variant, value)) = compound.into_iter().next() else {
+                    return Err(ProtocolError::Nbt("empty enum compound".to_owned()));
+                };
+                visitor.visit_enum(Variant { variant, value })
+            }
+            tag => Err(ProtocolError::Nbt(format!(
+                "an enum has to be a string or a compound with one key, not tag {}",
+                tag.id()
+            ))),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        seq tuple tuple_struct map struct identifier
+    }
+}
+
+struct CompoundAccess {
+    entries: std::vec::IntoIter<(String, Tag)>,
+    value: Option<Tag>,
+}
+
+impl CompoundAccess {
+    fn new(compound: Compound) -> Self {
+        Self {
+            entries: compound.into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for CompoundAccess {
+    type Error = ProtocolError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some((key, value)) = self.entries.next() else {
+            return Ok(None);
+        };
+
+        self.value = Some(value);
+        seed.deserialize(StringDeserializer::new(key)).map(Some)
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let tag = self
+            .value
+            .take()
+            .ok_or_else(|| ProtocolError::Nbt("compound value without a key".to_owned()))?;
+
+        seed.deserialize(Deserializer { tag })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+/// A variant that isn't just a name, from a compound with the variant as its only key
+struct Variant {
+    variant: String,
+    value: Tag,
+}
+
+impl<'de> EnumAccess<'de> for Variant {
+    type Error = ProtocolError;
+    type Variant = Deserializer;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let variant = seed.deserialize(StringDeserializer::<ProtocolError>::new(self.variant))?;
+        Ok((variant, Deserializer { tag: self.value }))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Deserializer {
+    type Error = ProtocolError;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Tag {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_newtype_struct(TAG, TagVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Compound {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Tag::deserialize(deserializer)? {
+            Tag::Compound(compound) => Ok(compound),
+            tag => Err(de::Error::custom(format!(
+                "expected a compound, got tag {}",
+                tag.id()
+            ))),
+        }
+    }
+}
+
+struct TagVisitor;
+
+impl<'de> Visitor<'de> for TagVisitor {
+    type Value = Tag;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("an NBT tag")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(Tag::Byte(i8::from(v)))
+    }
+
+    fn visit_i8<E: de::Error>(self, v: i8) -> Result<Self::Value, E> {
+        Ok(Tag::Byte(v))
+    }
+
+    fn visit_i16<E: de::Error>(self, v: i16) -> Result<Self::Value, E> {
+        Ok(Tag::Short(v))
+    }
+
+    fn visit_i32<E: de::Error>(self, v: i32) -> Result<Self::Value, E> {
+        Ok(Tag::Int(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Tag::Long(v))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        i64::try_from(v)
+            .map(Tag::Long)
+            .map_err(|_| E::custom(format!("{v} doesn't fit in a long")))
+    }
+
+    fn visit_f32<E: de::Error>(self, v: f32) -> Result<Self::Value, E> {
+        Ok(Tag::Float(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(Tag::Double(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Tag::String(v.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Tag::String(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(Tag::ByteArray(
+            v.iter().map(|byte| i8::from_be_bytes([*byte])).collect(),
+        ))
+    }
+
+    /// Other formats can wrap a tag in a newtype, which NBT has no use for
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut elements = vec![];
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+
+        Ok(Tag::List(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut compound = CompoundBuilder::default();
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                BYTE_ARRAY => return Ok(Tag::ByteArray(map.next_value()?)),
+                INT_ARRAY => return Ok(Tag::IntArray(map.next_value()?)),
+                LONG_ARRAY => return Ok(Tag::LongArray(map.next_value()?)),
+                _ => compound.insert(key, map.next_value::<Tag>()?),
+            }
+        }
+
+        Ok(Tag::Compound(compound.finish()))
+    }
+}
+
+impl de::Error for ProtocolError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self::Nbt(msg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_from_other_formats() {
+        // serde_json hands a newtype struct its contents as they are
+        let tag: Tag =
+            serde_json::from_str(r#"{"name":"copper","list":[1,2],"nested":{}}"#).unwrap();
+        assert_eq!(
+            tag,
+            Tag::Compound(Compound::from_iter([
+                ("name", Tag::from("copper")),
+                ("list", Tag::List(vec![Tag::Long(1), Tag::Long(2)])),
+                ("nested", Tag::Compound(Compound::new())),
+            ]))
+        );
+
+        let compound: Compound = serde_json::from_str(r#"{"a":1.5}"#).unwrap();
+        assert_eq!(compound, Compound::from_iter([("a", Tag::Double(1.5))]));
+    }
+}
@@ -0,0 +1,608 @@
+//! Rust values into [`Tag`]s
+//!
+//! Numbers map to the tag of their size, with unsigned ones having to fit the
+//! signed tag. Structs and maps become compounds and skip `None` fields,
+//! sequences become lists, and enums are the variant name (or a compound with
+//! the variant as its only key). Sequences of numbers are lists unless they
+//! go through [`byte_array`], [`int_array`] or [`long_array`].
+use serde::{
+    ser::{self, Impossible},
+    Serialize,
+};
+
+use super::{Compound, CompoundBuilder, Tag};
+use crate::ProtocolError;
+
+/// Newtype names that turn a list of numbers into the matching array tag
+pub const BYTE_ARRAY: &str = "__nbt_byte_array";
+pub const INT_ARRAY: &str = "__nbt_int_array";
+pub const LONG_ARRAY: &str = "__nbt_long_array";
+
+#[allow(dead_code)]
+pub fn to_tag<T: Serialize + ?Sized>(value: &T) -> Result<Tag, ProtocolError> {
+    value
+        .serialize(Serializer)?
+        .ok_or_else(|| ProtocolError::Nbt("nothing to serialize".to_owned()))
+}
+
+#[allow(dead_code)]
+pub fn to_compound<T: Serialize + ?Sized>(value: &T) -> Result<Compound, ProtocolError> {
+    match to_tag(value)? {
+        Tag::Compound(compound) => Ok(compound),
+        tag => Err(ProtocolError::Nbt(format!(
+            "expected a compound, got tag {}",
+            tag.id()
+        ))),
+    }
+}
+
+macro_rules! array_module {
+    ($module:ident, $name:ident, $element:ty, $doc:literal) => {
+        #[doc = $doc]
+        #[allow(dead_code)]
+        pub mod $module {
+            use serde::{Deserialize, Deserializer, Serializer};
+
+            pub fn serialize<S: Serializer>(
+                values: &[$element],
+                serializer: S,
+            ) -> Result<S::Ok, S::Error> {
+                serializer.serialize_newtype_struct(super::$name, values)
+            }
+
+            pub fn deserialize<'de, D: Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Vec<$element>, D::Error> {
+                Vec::deserialize(deserializer)
+            }
+        }
+    };
+}
+
+array_module!(
+    byte_array,
+    BYTE_ARRAY,
+    i8,
+    "`#[serde(with = \"nbt::byte_array\")]` on a `Vec<i8>` makes it a `TAG_Byte_Array`"
+);
+array_module!(
+    int_array,
+    INT_ARRAY,
+    i32,
+    "`#[serde(with = \"nbt::int_array\")]` on a `Vec<i32>` makes it a `TAG_Int_Array`"
+);
+array_module!(
+    long_array,
+    LONG_ARRAY,
+    i64,
+    "`#[serde(with = \"nbt::long_array\")]` on a `Vec<i64>` makes it a `TAG_Long_Array`"
+);
+
+fn unsupported(what: &str) -> ProtocolError {
+    ProtocolError::Nbt(format!("{what} has no NBT equivalent"))
+}
+
+fn out_of_range(value: impl std::fmt::Display) -> ProtocolError {
+    ProtocolError::Nbt(format!("{value} doesn't fit the signed tag of its size"))
+}
+
+/// Gives back `None` for `None` and `()`, which compounds leave out
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = VariantSerializer<ListSerializer>;
+    type SerializeMap = CompoundSerializer;
+    type SerializeStruct = CompoundSerializer;
+    type SerializeStructVariant = VariantSerializer<CompoundSerializer>;
+
+    fn serialize_bool(self, v: bool) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Byte(i8::from(v))))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Byte(v)))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Short(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Int(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Long(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i8(i8::try_from(v).map_err(|_| out_of_range(v))?)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i16(i16::try_from(v).map_err(|_| out_of_range(v))?)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i32(i32::try_from(v).map_err(|_| out_of_range(v))?)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        self.serialize_i64(i64::try_from(v).map_err(|_| out_of_range(v))?)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Float(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Double(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::String(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::String(v.to_owned())))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::ByteArray(
+            v.iter().map(|byte| i8::from_be_bytes([*byte])).collect(),
+        )))
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(Tag::Compound(Compound::new())))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        let tag = value.serialize(self)?;
+
+        let array = match name {
+            BYTE_ARRAY => Tag::ByteArray(array_elements(tag, |tag| match tag {
+                Tag::Byte(value) => Some(value),
+                _ => None,
+            })?),
+            INT_ARRAY => Tag::IntArray(array_elements(tag, |tag| match tag {
+                Tag::Int(value) => Some(value),
+                _ => None,
+            })?),
+            LONG_ARRAY => Tag::LongArray(array_elements(tag, |tag| match tag {
+                Tag::Long(value) => Some(value),
+                _ => None,
+            })?),
+            _ => return Ok(tag),
+        };
+
+        Ok(Some(array))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        let mut compound = Compound::new();
+        if let Some(tag) = value.serialize(self)? {
+            compound.insert(variant, tag);
+        }
+
+        Ok(Some(Tag::Compound(compound)))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Ok(ListSerializer {
+            elements: Vec::with_capacity(len.unwrap_or_default()),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Ok(CompoundSerializer {
+            compound: CompoundBuilder::default(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+/// Unwrap a list of numbers into the elements of an array tag
+fn array_elements<T>(
+    tag: Option<Tag>,
+    element: impl Fn(Tag) -> Option<T>,
+) -> Result<Vec<T>, ProtocolError> {
+    let Some(Tag::List(elements)) = tag else {
+        return Err(ProtocolError::Nbt(
+            "an array has to be a sequence".to_owned(),
+        ));
+    };
+
+    elements
+        .into_iter()
+        .map(|tag| {
+            let id = tag.id();
+            element(tag)
+                .ok_or_else(|| ProtocolError::Nbt(format!("tag {id} in an array of another type")))
+        })
+        .collect()
+}
+
+struct ListSerializer {
+    elements: Vec<Tag>,
+}
+
+impl ListSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ProtocolError> {
+        let tag = value
+            .serialize(Serializer)?
+            .ok_or_else(|| unsupported("a missing value in a list"))?;
+
+        // checked here instead of when writing, so the error points at the value
+        if let Some(first) = self.elements.first() {
+            if first.id() != tag.id() {
+                return Err(ProtocolError::Nbt(
+                    "list with more than one kind of tag".to_owned(),
+                ));
+            }
+        }
+
+        self.elements.push(tag);
+        Ok(())
+    }
+
+    fn finish(self) -> Tag {
+        Tag::List(self.elements)
+    }
+}
+
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.finish()))
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.finish()))
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.finish()))
+    }
+}
+
+struct CompoundSerializer {
+    compound: CompoundBuilder,
+    /// The key of a map entry, waiting for its value
+    key: Option<String>,
+}
+
+impl CompoundSerializer {
+    fn insert<T: Serialize + ?Sized>(
+        &mut self,
+        key: String,
+        value: &T,
+    ) -> Result<(), ProtocolError> {
+        if let Some(tag) = value.serialize(Serializer)? {
+            self.compound.insert(key, tag);
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Tag {
+        Tag::Compound(self.compound.finish())
+    }
+}
+
+impl ser::SerializeMap for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Self::Error> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ProtocolError::Nbt("map value without a key".to_owned()))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.finish()))
+    }
+}
+
+impl ser::SerializeStruct for CompoundSerializer {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        Ok(Some(self.finish()))
+    }
+}
+
+/// A tuple or struct variant, which ends up as a compound with the variant as its only key
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> VariantSerializer<S> {
+    fn finish(self, tag: Tag) -> Tag {
+        let mut compound = Compound::new();
+        compound.insert(self.variant, tag);
+        Tag::Compound(compound)
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<ListSerializer> {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.inner.push(value)
+    }
+
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        let list = std::mem::take(&mut self.inner.elements);
+        Ok(Some(self.finish(Tag::List(list))))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<CompoundSerializer> {
+    type Ok = Option<Tag>;
+    type Error = ProtocolError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error> {
+        self.inner.insert(key.to_owned(), value)
+    }
+
+    fn end(mut self) -> Result<Self::Ok, Self::Error> {
+        let compound = std::mem::take(&mut self.inner.compound);
+        Ok(Some(self.finish(Tag::Compound(compound.finish()))))
+    }
+}
+
+fn not_a_key() -> ProtocolError {
+    unsupported("a compound key that isn't a string")
+}
+
+/// Every other kind of value, which can't be a key
+macro_rules! reject_keys {
+    ($($method:ident($($argument:ty),*) -> $ok:ty;)*) => {
+        $(
+            fn $method(self, $(_: $argument),*) -> Result<$ok, Self::Error> {
+                Err(not_a_key())
+            }
+        )*
+    };
+}
+
+/// Compound keys are strings, so only things that are already text can be one
+struct KeySerializer;
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = ProtocolError;
+
+    type SerializeSeq = Impossible<String, ProtocolError>;
+    type SerializeTuple = Impossible<String, ProtocolError>;
+    type SerializeTupleStruct = Impossible<String, ProtocolError>;
+    type SerializeTupleVariant = Impossible<String, ProtocolError>;
+    type SerializeMap = Impossible<String, ProtocolError>;
+    type SerializeStruct = Impossible<String, ProtocolError>;
+    type SerializeStructVariant = Impossible<String, ProtocolError>;
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_owned())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<Self::Ok, Self::Error> {
+        Err(not_a_key())
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Self::Ok, Self::Error> {
+        Err(not_a_key())
+    }
+
+    reject_keys! {
+        serialize_bool(bool) -> Self::Ok;
+        serialize_i8(i8) -> Self::Ok;
+        serialize_i16(i16) -> Self::Ok;
+        serialize_i32(i32) -> Self::Ok;
+        serialize_i64(i64) -> Self::Ok;
+        serialize_u8(u8) -> Self::Ok;
+        serialize_u16(u16) -> Self::Ok;
+        serialize_u32(u32) -> Self::Ok;
+        serialize_u64(u64) -> Self::Ok;
+        serialize_f32(f32) -> Self::Ok;
+        serialize_f64(f64) -> Self::Ok;
+        serialize_bytes(&[u8]) -> Self::Ok;
+        serialize_none() -> Self::Ok;
+        serialize_unit() -> Self::Ok;
+        serialize_unit_struct(&'static str) -> Self::Ok;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+impl Serialize for Tag {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Byte(value) => serializer.serialize_i8(*value),
+            Self::Short(value) => serializer.serialize_i16(*value),
+            Self::Int(value) => serializer.serialize_i32(*value),
+            Self::Long(value) => serializer.serialize_i64(*value),
+            Self::Float(value) => serializer.serialize_f32(*value),
+            Self::Double(value) => serializer.serialize_f64(*value),
+            Self::ByteArray(values) => serializer.serialize_newtype_struct(BYTE_ARRAY, values),
+            Self::String(value) => serializer.serialize_str(value),
+            Self::List(elements) => serializer.collect_seq(elements),
+            Self::Compound(compound) => compound.serialize(serializer),
+            Self::IntArray(values) => serializer.serialize_newtype_struct(INT_ARRAY, values),
+            Self::LongArray(values) => serializer.serialize_newtype_struct(LONG_ARRAY, values),
+        }
+    }
+}
+
+impl Serialize for Compound {
+    fn serialize<S: ser::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl ser::Error for ProtocolError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Self::Nbt(msg.to_string())
+    }
+}
@@ -0,0 +1,685 @@
+//! SNBT, the text form of NBT that commands like `/give` and `/data` use
+//!
+//! Parsing follows vanilla: unquoted words that look like numbers are numbers
+//! (`b`, `s`, `L`, `f` and `d` pick the type, a bare integer is an int and a
+//! bare decimal a double), `true` and `false` are bytes, and any other word is
+//! a string. Printing is canonical, every tag gets the suffix or quotes that
+//! parse back to exactly that tag, except infinite and NaN floats which SNBT
+//! can't write at all. `{:#}` spreads it over lines for logs.
+use std::{
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+use super::{Compound, CompoundBuilder, Tag, MAX_DEPTH};
+use crate::ProtocolError;
+
+const INDENT: &str = "    ";
+
+impl FromStr for Tag {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s).all(|parser| parser.value(0))
+    }
+}
+
+/// What commands take, the outermost tag has to be a compound
+impl FromStr for Compound {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s).all(|parser| parser.compound(0))
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    /// In bytes, for errors
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    const fn new(input: &'a str) -> Self {
+        Self { input, position: 0 }
+    }
+
+    /// Run `parse` and make sure it used up the whole input
+    fn all<T>(
+        mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ProtocolError>,
+    ) -> Result<T, ProtocolError> {
+        let parsed = parse(&mut self)?;
+
+        self.skip_whitespace();
+        if self.peek().is_some() {
+            return Err(self.error("trailing characters"));
+        }
+
+        Ok(parsed)
+    }
+
+    fn error(&self, message: impl fmt::Display) -> ProtocolError {
+        ProtocolError::Nbt(format!("{message} at position {} of SNBT", self.position))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.position..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.next();
+        }
+    }
+
+    /// Skip whitespace and then `expected` if it's there
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+
+        let found = self.peek() == Some(expected);
+        if found {
+            self.next();
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ProtocolError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected '{expected}'")))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Tag, ProtocolError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error(format!("nested deeper than {MAX_DEPTH} levels")));
+        }
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.compound(depth).map(Tag::Compound),
+            Some('[') => self.list_or_array(depth),
+            Some('"' | '\'') => self.quoted().map(Tag::String),
+            _ => Ok(primitive(self.unquoted()?)),
+        }
+    }
+
+    fn compound(&mut self, depth: usize) -> Result<Compound, ProtocolError> {
+        self.expect('{')?;
+
+        let mut compound = CompoundBuilder::default();
+        loop {
+            if self.eat('}') {
+                return Ok(compound.finish());
+            }
+
+            let key = self.key()?;
+            self.expect(':')?;
+            compound.insert(key, self.value(depth + 1)?);
+
+            if !self.eat(',') {
+                self.expect('}')?;
+                return Ok(compound.finish());
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ProtocolError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('"' | '\'') => self.quoted(),
+            _ => self.unquoted().map(str::to_owned),
+        }
+    }
+
+    fn list_or_array(&mut self, depth: usize) -> Result<Tag, ProtocolError> {
+        self.expect('[')?;
+
+        // `[B;`, `[I;` and `[L;` start arrays, a list can't have a `;` there
+        let mut ahead = self.input[self.position..].chars();
+        let (Some(kind), Some(';')) = (ahead.next(), ahead.next()) else {
+            return self.list(depth);
+        };
+        if matches!(kind, '"' | '\'') {
+            return self.list(depth);
+        }
+
+        self.position += kind.len_utf8() + 1;
+        match kind {
+            'B' => self
+                .array(depth, kind, |tag| match tag {
+                    Tag::Byte(value) => Some(value),
+                    _ => None,
+                })
+                .map(Tag::ByteArray),
+            'I' => self
+                .array(depth, kind, |tag| match tag {
+                    Tag::Int(value) => Some(value),
+                    _ => None,
+                })
+                .map(Tag::IntArray),
+            'L' => self
+                .array(depth, kind, |tag| match tag {
+                    Tag::Long(value) => Some(value),
+                    _ => None,
+                })
+                .map(Tag::LongArray),
+            _ => Err(self.error(format!("unknown array type '{kind}'"))),
+        }
+    }
+
+    fn list(&mut self, depth: usize) -> Result<Tag, ProtocolError> {
+        let elements = self.elements(depth)?;
+
+        if let Some(first) = elements.first() {
+            if elements.iter().any(|element| element.id() != first.id()) {
+                return Err(self.error("list with more than one kind of tag"));
+            }
+        }
+
+        Ok(Tag::List(elements))
+    }
+
+    /// Every element has to be the array's own type, `[L;1,2]` is an error like in vanilla
+    fn array<T>(
+        &mut self,
+        depth: usize,
+        kind: char,
+        element: impl Fn(Tag) -> Option<T>,
+    ) -> Result<Vec<T>, ProtocolError> {
+        self.elements(depth)?
+            .into_iter()
+            .map(|tag| {
+                let id = tag.id();
+                element(tag).ok_or_else(|| self.error(format!("tag {id} in a [{kind};] array")))
+            })
+            .collect()
+    }
+
+    /// What's left of a list or array after the `[`, up to and including the `]`
+    fn elements(&mut self, depth: usize) -> Result<Vec<Tag>, ProtocolError> {
+        let mut elements = vec![];
+        loop {
+            if self.eat(']') {
+                return Ok(elements);
+            }
+
+            elements.push(self.value(depth + 1)?);
+
+            if !self.eat(',') {
+                self.expect(']')?;
+                return Ok(elements);
+            }
+        }
+    }
+
+    /// A string in `"` or `'`, where `\` only escapes itself and the quote
+    fn quoted(&mut self) -> Result<String, ProtocolError> {
+        let Some(quote) = self.next() else {
+            return Err(self.error("expected a quote"));
+        };
+
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('\\') => match self.next() {
+                    Some(c) if c == quote || c == '\\' => text.push(c),
+                    Some(c) => return Err(self.error(format!("invalid escape '\\{c}'"))),
+                    None => return Err(self.error("unclosed string")),
+                },
+                Some(c) if c == quote => return Ok(text),
+                Some(c) => text.push(c),
+                None => return Err(self.error("unclosed string")),
+            }
+        }
+    }
+
+    fn unquoted(&mut self) -> Result<&'a str, ProtocolError> {
+        let start = self.position;
+        while self.peek().is_some_and(is_unquoted) {
+            self.next();
+        }
+
+        if start == self.position {
+            return Err(self.error("expected a value"));
+        }
+
+        Ok(&self.input[start..self.position])
+    }
+}
+
+const fn is_unquoted(c: char) -> bool {
+    matches!(c, '0'..='9' | 'A'..='Z' | 'a'..='z' | '_' | '-' | '.' | '+')
+}
+
+/// An unquoted word, numbers that don't fit their type stay strings like in vanilla
+fn primitive(word: &str) -> Tag {
+    match word {
+        "true" => return Tag::Byte(1),
+        "false" => return Tag::Byte(0),
+        _ => {}
+    }
+
+    // unquoted words are ascii, so this can't split a character
+    let (number, suffix) = word.split_at(word.len() - 1);
+    let tag = match suffix.to_ascii_lowercase().as_str() {
+        "b" if is_integer(number) => number.parse().ok().map(Tag::Byte),
+        "s" if is_integer(number) => number.parse().ok().map(Tag::Short),
+        "l" if is_integer(number) => number.parse().ok().map(Tag::Long),
+        "f" if is_decimal(number, false) => number.parse().ok().map(Tag::Float),
+        "d" if is_decimal(number, false) => number.parse().ok().map(Tag::Double),
+        _ if is_integer(word) => word.parse().ok().map(Tag::Int),
+        _ if is_decimal(word, true) => word.parse().ok().map(Tag::Double),
+        _ => None,
+    };
+
+    tag.unwrap_or_else(|| Tag::String(word.to_owned()))
+}
+
+fn strip_sign(number: &str) -> &str {
+    number.strip_prefix(['+', '-']).unwrap_or(number)
+}
+
+/// `0` or digits without a leading zero, with an optional sign
+fn is_integer(number: &str) -> bool {
+    match strip_sign(number).as_bytes() {
+        [b'0'] => true,
+        [b'1'..=b'9', rest @ ..] => rest.iter().all(u8::is_ascii_digit),
+        _ => false,
+    }
+}
+
+/// Digits with a `.` somewhere (it has to be there without a suffix) and an optional exponent
+fn is_decimal(number: &str, needs_point: bool) -> bool {
+    let number = strip_sign(number);
+    let (mantissa, exponent) = match number.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(strip_sign(exponent))),
+        None => (number, None),
+    };
+
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None if needs_point => return false,
+        None => (mantissa, None),
+    };
+
+    let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    let has_digits = !whole.is_empty() || fraction.is_some_and(|fraction| !fraction.is_empty());
+
+    has_digits
+        && digits(whole)
+        && fraction.is_none_or(digits)
+        && exponent.is_none_or(|exponent| !exponent.is_empty() && digits(exponent))
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_tag(f, self, 0)
+    }
+}
+
+impl fmt::Display for Compound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_compound(f, self, 0)
+    }
+}
+
+fn write_tag(f: &mut fmt::Formatter<'_>, tag: &Tag, depth: usize) -> fmt::Result {
+    match tag {
+        Tag::Byte(value) => write!(f, "{value}b"),
+        Tag::Short(value) => write!(f, "{value}s"),
+        Tag::Int(value) => write!(f, "{value}"),
+        Tag::Long(value) => write!(f, "{value}L"),
+        Tag::Float(value) => write_decimal(f, *value, 'f'),
+        Tag::Double(value) => write_decimal(f, *value, 'd'),
+        Tag::ByteArray(values) => write_array(f, 'B', values, "b"),
+        Tag::String(value) => write_quoted(f, value),
+        Tag::List(elements) => write_list(f, elements, depth),
+        Tag::Compound(compound) => write_compound(f, compound, depth),
+        Tag::IntArray(values) => write_array(f, 'I', values, ""),
+        Tag::LongArray(values) => write_array(f, 'L', values, "L"),
+    }
+}
+
+/// SNBT has no way to write infinity or NaN, those get Java's spelling like in vanilla and
+/// read back as strings
+fn write_decimal<T>(f: &mut fmt::Formatter<'_>, value: T, suffix: char) -> fmt::Result
+where
+    T: fmt::Debug + Into<f64> + Copy,
+{
+    let wide: f64 = value.into();
+
+    if wide.is_nan() {
+        write!(f, "NaN{suffix}")
+    } else if wide.is_infinite() {
+        let sign = if wide < 0.0 { "-" } else { "" };
+        write!(f, "{sign}Infinity{suffix}")
+    } else {
+        // debug always has a `.0` or an exponent, so it reads like vanilla's
+        write!(f, "{value:?}{suffix}")
+    }
+}
+
+fn write_compound(f: &mut fmt::Formatter<'_>, compound: &Compound, depth: usize) -> fmt::Result {
+    f.write_char('{')?;
+
+    for (i, (key, tag)) in compound.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        write_newline(f, depth + 1)?;
+
+        if !key.is_empty() && key.chars().all(is_unquoted) {
+            f.write_str(key)?;
+        } else {
+            write_quoted(f, key)?;
+        }
+
+        f.write_str(if f.alternate() { ": " } else { ":" })?;
+        write_tag(f, tag, depth + 1)?;
+    }
+
+    if !compound.is_empty() {
+        write_newline(f, depth)?;
+    }
+    f.write_char('}')
+}
+
+/// Lists of lists or compounds get a line per element when pretty, anything else stays on one
+fn write_list(f: &mut fmt::Formatter<'_>, elements: &[Tag], depth: usize) -> fmt::Result {
+    let spread = matches!(elements.first(), Some(Tag::List(_) | Tag::Compound(_)));
+
+    f.write_char('[')?;
+
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+
+        if spread {
+            write_newline(f, depth + 1)?;
+        } else if i > 0 && f.alternate() {
+            f.write_char(' ')?;
+        }
+
+        write_tag(f, element, depth + 1)?;
+    }
+
+    if spread {
+        write_newline(f, depth)?;
+    }
+    f.write_char(']')
+}
+
+fn write_array<T: fmt::Display>(
+    f: &mut fmt::Formatter<'_>,
+    kind: char,
+    values: &[T],
+    suffix: &str,
+) -> fmt::Result {
+    write!(f, "[{kind};")?;
+
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            f.write_char(',')?;
+        }
+        if f.alternate() {
+            f.write_char(' ')?;
+        }
+
+        write!(f, "{value}{suffix}")?;
+    }
+
+    f.write_char(']')
+}
+
+/// Quoted with `'` if a `"` comes before any `'`, otherwise `"`, so there's less to escape
+fn write_quoted(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    let quote = match text.chars().find(|c| matches!(c, '"' | '\'')) {
+        Some('"') => '\'',
+        _ => '"',
+    };
+
+    f.write_char(quote)?;
+    for c in text.chars() {
+        if c == quote || c == '\\' {
+            f.write_char('\\')?;
+        }
+        f.write_char(c)?;
+    }
+    f.write_char(quote)
+}
+
+fn write_newline(f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+    if f.alternate() {
+        f.write_char('\n')?;
+        for _ in 0..depth {
+            f.write_str(INDENT)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(snbt: &str) -> Tag {
+        snbt.parse().unwrap()
+    }
+
+    /// Printed both ways, `tag` has to come back as itself
+    fn assert_round_trip(tag: &Tag) {
+        assert_eq!(&parse(&tag.to_string()), tag, "{tag}");
+        assert_eq!(&parse(&format!("{tag:#}")), tag, "{tag:#}");
+    }
+
+    #[test]
+    fn typed_arrays() {
+        assert_eq!(parse("[B;1b,-2B]"), Tag::ByteArray(vec![1, -2]));
+        assert_eq!(parse("[B;true,false]"), Tag::ByteArray(vec![1, 0]));
+        assert_eq!(parse("[I; 1, -2 ]"), Tag::IntArray(vec![1, -2]));
+        assert_eq!(parse("[L;5L,6l]"), Tag::LongArray(vec![5, 6]));
+        assert_eq!(parse("[L;]"), Tag::LongArray(vec![]));
+
+        assert_eq!(Tag::ByteArray(vec![1, -2]).to_string(), "[B;1b,-2b]");
+        assert_eq!(Tag::IntArray(vec![1, -2]).to_string(), "[I;1,-2]");
+        assert_eq!(Tag::LongArray(vec![5]).to_string(), "[L;5L]");
+        assert_eq!(format!("{:#}", Tag::IntArray(vec![1, 2])), "[I; 1, 2]");
+
+        for invalid in ["[L;1,2]", "[B;1]", "[I;1b]", "[X;1]", "[I;1"] {
+            assert!(invalid.parse::<Tag>().is_err(), "{invalid} was accepted");
+        }
+
+        // a quoted string can't be an array type
+        assert_eq!(
+            parse("['B;x']"),
+            Tag::List(vec![Tag::String("B;x".to_owned())])
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(parse("1b"), Tag::Byte(1));
+        assert_eq!(parse("-3S"), Tag::Short(-3));
+        assert_eq!(parse("+7"), Tag::Int(7));
+        assert_eq!(parse("9l"), Tag::Long(9));
+        assert_eq!(parse("9L"), Tag::Long(9));
+        assert_eq!(parse("1.5f"), Tag::Float(1.5));
+        assert_eq!(parse("1F"), Tag::Float(1.0));
+        assert_eq!(parse("2e3d"), Tag::Double(2000.0));
+        assert_eq!(parse("1.5"), Tag::Double(1.5));
+        assert_eq!(parse(".5"), Tag::Double(0.5));
+        assert_eq!(parse("1."), Tag::Double(1.0));
+        assert_eq!(parse("1.e2"), Tag::Double(100.0));
+        assert_eq!(parse("true"), Tag::Byte(1));
+        assert_eq!(parse("false"), Tag::Byte(0));
+
+        for tag in [
+            Tag::Byte(i8::MIN),
+            Tag::Short(i16::MAX),
+            Tag::Int(i32::MIN),
+            Tag::Long(i64::MAX),
+            Tag::Float(f32::MAX),
+            Tag::Float(-0.0),
+            Tag::Double(f64::MIN_POSITIVE),
+            Tag::Double(1e20),
+        ] {
+            assert_round_trip(&tag);
+        }
+    }
+
+    #[test]
+    fn out_of_range_and_other_words_are_strings() {
+        for word in [
+            "300b",
+            "40000s",
+            "3000000000",
+            "1e5",
+            "01",
+            "stone",
+            "b",
+            "1.2.3",
+        ] {
+            assert_eq!(parse(word), Tag::String(word.to_owned()));
+        }
+    }
+
+    #[test]
+    fn non_finite_floats_print_like_vanilla() {
+        assert_eq!(Tag::Float(f32::INFINITY).to_string(), "Infinityf");
+        assert_eq!(Tag::Double(f64::NEG_INFINITY).to_string(), "-Infinityd");
+        assert_eq!(Tag::Float(f32::NAN).to_string(), "NaNf");
+
+        // which, like in vanilla, don't come back as numbers
+        assert_eq!(parse("Infinityf"), Tag::String("Infinityf".to_owned()));
+        assert_eq!(parse("NaNd"), Tag::String("NaNd".to_owned()));
+    }
+
+    #[test]
+    // the snbt in here looks a lot like format arguments
+    #[allow(clippy::literal_string_with_formatting_args)]
+    fn keys() {
+        let compound: Compound = r#"{plain:1,"with space":2,'single':3,"":4,"a\"b":5}"#
+            .parse()
+            .unwrap();
+        let keys: Vec<_> = compound.iter().map(|(key, _)| key).collect();
+        assert_eq!(keys, ["plain", "with space", "single", "", "a\"b"]);
+
+        assert_eq!(
+            compound.to_string(),
+            r#"{plain:1,"with space":2,single:3,"":4,'a"b':5}"#
+        );
+
+        // the last of a repeated key wins, where the first one was
+        let repeated: Compound = "{a:1,b:2,a:3}".parse().unwrap();
+        assert_eq!(repeated.to_string(), "{a:3,b:2}");
+
+        for invalid in ["{:1}", "{a 1}", "{a:1", "{a:1,,b:2}", "{a:}"] {
+            assert!(
+                invalid.parse::<Compound>().is_err(),
+                "{invalid} was accepted"
+            );
+        }
+        assert!("1".parse::<Compound>().is_err());
+    }
+
+    #[test]
+    fn quotes_and_escapes() {
+        assert_eq!(parse(r#""a'b""#), Tag::String("a'b".to_owned()));
+        assert_eq!(parse(r"'a\'b'"), Tag::String("a'b".to_owned()));
+        assert_eq!(parse(r#""a\"b""#), Tag::String("a\"b".to_owned()));
+        assert_eq!(parse(r#"'a"b'"#), Tag::String("a\"b".to_owned()));
+        assert_eq!(
+            parse(r#""back\\slash""#),
+            Tag::String("back\\slash".to_owned())
+        );
+
+        for invalid in [r#""\n""#, r#""\'""#, r#"'\"'"#, r#""unclosed"#, r#""\"#] {
+            assert!(invalid.parse::<Tag>().is_err(), "{invalid} was accepted");
+        }
+
+        // whichever quote needs less escaping
+        assert_eq!(Tag::String("a'b".to_owned()).to_string(), r#""a'b""#);
+        assert_eq!(Tag::String("a\"b".to_owned()).to_string(), r#"'a"b'"#);
+        assert_eq!(Tag::String("\"'".to_owned()).to_string(), r#"'"\''"#);
+        assert_eq!(Tag::String("\\".to_owned()).to_string(), r#""\\""#);
+
+        for text in ["", "a'b", "a\"b", "\"'\\", "new\nline", "ünïcödé 😀"] {
+            assert_round_trip(&Tag::String(text.to_owned()));
+        }
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(parse("[1,2,]"), Tag::List(vec![Tag::Int(1), Tag::Int(2)]));
+        assert_eq!(parse("[ ]"), Tag::List(vec![]));
+        assert!("[1,2b]".parse::<Tag>().is_err());
+        assert!("[1,,2]".parse::<Tag>().is_err());
+    }
+
+    #[test]
+    fn nesting_up_to_max_depth() {
+        let nested = |levels: usize| "[".repeat(levels) + &"]".repeat(levels);
+
+        assert!(nested(MAX_DEPTH + 1).parse::<Tag>().is_ok());
+        assert!(nested(MAX_DEPTH + 2).parse::<Tag>().is_err());
+
+        let compounds = "{a:".repeat(MAX_DEPTH + 2) + "1" + &"}".repeat(MAX_DEPTH + 2);
+        assert!(compounds.parse::<Tag>().is_err());
+    }
+
+    #[test]
+    fn trailing_characters() {
+        assert!("1 2".parse::<Tag>().is_err());
+        assert!("{} x".parse::<Tag>().is_err());
+        assert!("".parse::<Tag>().is_err());
+        assert_eq!(parse("  1  "), Tag::Int(1));
+    }
+
+    #[test]
+    fn display_parses_back() {
+        let item: Compound = r#"{
+            display: {Name: '{"text":"Hi"}', Lore: ["a", "b's"]},
+            Count: 3b, Damage: 0s, id: "minecraft:stone", seed: -4L,
+            pos: [1.5d, 64.0d, -2.25d], rot: [90.0f, 0f],
+            ba: [B; 1b], ia: [I;], la: [L; 5L],
+            list: [{a: 1}, {b: [[1], []]}], empty: {}, none: []
+        }"#
+        .parse()
+        .unwrap();
+
+        let tag = Tag::Compound(item);
+        assert_round_trip(&tag);
+
+        // and printing what was parsed gives the same text again
+        let printed = tag.to_string();
+        assert_eq!(parse(&printed).to_string(), printed);
+        assert_eq!(
+            printed,
+            concat!(
+                r#"{display:{Name:'{"text":"Hi"}',Lore:["a","b's"]},Count:3b,Damage:0s,"#,
+                r#"id:"minecraft:stone",seed:-4L,pos:[1.5d,64.0d,-2.25d],rot:[90.0f,0.0f],"#,
+                r#"ba:[B;1b],ia:[I;],la:[L;5L],list:[{a:1},{b:[[1],[]]}],empty:{},none:[]}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn pretty() {
+        let compound: Compound = "{a:{b:1},c:[{}],d:[1,2]}".parse().unwrap();
+        assert_eq!(
+            format!("{compound:#}"),
+            "{\n    a: {\n        b: 1\n    },\n    c: [\n        {}\n    ],\n    d: [1, 2]\n}"
+        );
+    }
+}
@@ -7,7 +7,14 @@ use std::io::{Cursor, Read, Write};
 
 use tracing::error;
 
-use crate::{handshaking, login, play, status, ProtocolError, State};
+use crate::{
+    data_types::{DataType, RemainingBytes},
+    handshaking, login, play,
+    server::Server,
+    status, ProtocolError, State,
+};
+
+pub use copper_macros::Packet;
 
 pub trait Encodable {
     fn write_to<W: Write>(&self, writer: &mut W) -> Result<usize, ProtocolError>;
@@ -17,6 +24,38 @@ pub trait Decodable: Sized {
     fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError>;
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    ServerBound,
+    ClientBound,
+}
+
+/// Where a packet belongs, filled in by `#[derive(Packet)]` from its `#[packet(...)]`
+///
+/// The derived `Encodable` writes the id in front of the fields, while `Decodable`
+/// only reads the fields, since the id was already read to know which packet it is.
+pub trait Packet {
+    const ID: i32;
+    const STATE: State;
+    const DIRECTION: Direction;
+}
+
+/// Read the rest of a packet after its id, which has to use up every byte of it
+pub fn decode<P, R>(reader: &mut R) -> Result<P, ProtocolError>
+where
+    P: Packet + Decodable,
+    R: Read,
+{
+    let packet = P::read_from(reader)?;
+
+    // leftovers mean the client sent something else than we think it did
+    if !RemainingBytes::read_from(reader)?.0.is_empty() {
+        return Err(ProtocolError::Malformed);
+    }
+
+    Ok(packet)
+}
+
 // #[derive(Debug)]
 // struct Packet(Vec<u8>);
 // // TODO list
@@ -73,31 +112,25 @@ pub enum ClientBound {
 }
 
 impl ClientBound {
+    /// The answer to a request, `None` for the ones that don't get one
     pub fn create_reply(
-        // is this even needed?
-        // stream: &mut tokio::net::TcpStream,
-        state: &State,
         request: ServerBound,
-    ) -> Result<Self, ProtocolError> {
+        server: &Server,
+    ) -> Result<Option<Self>, ProtocolError> {
         match request {
-            ServerBound::Handshake(req) => {
+            ServerBound::Handshake(_) => {
                 error!("Handshaking packet for clientbound?");
                 Err(ProtocolError::Internal)
             }
-            ServerBound::Status(req) => Ok(Self::Status(status::ClientBound::from_request(req)?)),
-            ServerBound::Login(req) => Ok(Self::Login(login::ClientBound::from_request(req)?)),
-            ServerBound::Play(req) => Ok(Self::Play(play::ClientBound::from_request(req)?)),
+            ServerBound::Status(req) => Ok(Some(Self::Status(status::ClientBound::from_request(
+                &req, server,
+            )?))),
+            ServerBound::Login(_) => {
+                error!("Login packets are answered by the login sequence, not one by one");
+                Err(ProtocolError::Internal)
+            }
+            ServerBound::Play(req) => Ok(play::ClientBound::from_request(&req).map(Self::Play)),
         }
-
-        // match state {
-        //     State::Handshaking => {
-        //         error!("Handshaking packet for clientbound?");
-        //         Err(ProtocolError::Internal)
-        //     }
-        //     State::Status => Ok(Self::Status(status::ClientBound::from_request(request)?)),
-        //     State::Login => Ok(Self::Login(login::ClientBound::from_request(request)?)),
-        //     State::Play => Err(ProtocolError::Unimplemented),
-        // }
     }
 
     // pub fn write_to(&self, stream: &mut tokio::net::TcpStream) -> Result<usize, ProtocolError> {
@@ -120,7 +153,7 @@ impl ClientBound {
         match self {
             Self::Status(res) => res.write_to(&mut cursor),
             Self::Login(res) => res.write_to(&mut cursor),
-            Self::Play(_) => todo!(),
+            Self::Play(res) => res.write_to(&mut cursor),
         }?;
 
         Ok(encoded_packet)
@@ -135,19 +168,3 @@ pub enum ServerBound {
     Login(login::ServerBound),
     Play(play::ServerBound),
 }
-
-impl ServerBound {
-    pub fn parse_packet<R: Read>(reader: &mut R, state: &State) -> Result<Self, ProtocolError> {
-        match state {
-            State::Handshaking => Ok(Self::Handshake(handshaking::ServerBound::read_from(
-                reader,
-            )?)),
-            State::Status => Ok(Self::Status(status::ServerBound::read_from(reader)?)),
-            State::Login => Ok(Self::Login(login::ServerBound::read_from(reader)?)),
-            State::Play => {
-                error!("unimplemented");
-                Err(ProtocolError::Unimplemented)
-            }
-        }
-    }
-}
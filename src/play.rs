@@ -1,18 +1,83 @@
-use crate::ProtocolError;
+use std::io::Write;
 
+use crate::{
+    chat::TextComponent,
+    data_types::{ProtocolString, VarInt},
+    packet::{Encodable, Packet},
+    ProtocolError,
+};
+
+/// The few play packets we can read, none of which get answered until play is implemented
+// only logged for now, nothing looks inside them yet
+#[allow(dead_code)]
 #[derive(Debug)]
 pub enum ServerBound {
-    BundleDelimiter(BundleDelimiter),
+    ConfirmTeleportation(ConfirmTeleportation),
+    ClientInformation(ClientInformation),
+    KeepAlive(KeepAlive),
 }
 
-#[derive(Debug)]
-struct BundleDelimiter;
+#[derive(Debug, Packet)]
+#[packet(id = 0x00, state = Play, direction = ServerBound)]
+pub struct ConfirmTeleportation {
+    pub teleport_id: VarInt,
+}
+
+/// Sent right after joining and whenever the player changes their settings
+#[derive(Debug, Packet)]
+#[packet(id = 0x08, state = Play, direction = ServerBound)]
+pub struct ClientInformation {
+    pub locale: ProtocolString,
+    pub view_distance: i8,
+    pub chat_mode: VarInt,
+    pub chat_colors: bool,
+    pub displayed_skin_parts: u8,
+    pub main_hand: VarInt,
+    pub enable_text_filtering: bool,
+    pub allow_server_listings: bool,
+}
+
+#[derive(Debug, Packet)]
+#[packet(id = 0x12, state = Play, direction = ServerBound)]
+pub struct KeepAlive {
+    pub keep_alive_id: i64,
+}
 
 #[derive(Debug)]
-pub enum ClientBound {}
+pub enum ClientBound {
+    Disconnect(Disconnect),
+}
 
 impl ClientBound {
-    pub fn from_request(request: ServerBound) -> Result<Self, ProtocolError> {
-        Err(ProtocolError::Unimplemented)
+    pub fn write_to<W>(&self, writer: &mut W) -> Result<usize, ProtocolError>
+    where
+        W: Write,
+    {
+        match self {
+            Self::Disconnect(packet) => packet.write_to(writer),
+        }
+    }
+
+    /// Nothing in play gets an answer yet, reading them only keeps them from kicking the player
+    pub const fn from_request(request: &ServerBound) -> Option<Self> {
+        match request {
+            ServerBound::ConfirmTeleportation(_)
+            | ServerBound::ClientInformation(_)
+            | ServerBound::KeepAlive(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Packet)]
+#[packet(id = 0x1A, state = Play, direction = ClientBound)]
+pub struct Disconnect {
+    reason: ProtocolString,
+}
+
+impl Disconnect {
+    pub fn new(reason: &TextComponent) -> Result<Self, ProtocolError> {
+        Ok(Self {
+            reason: ProtocolString::try_from(serde_json::to_string(reason)?)?,
+        })
     }
 }
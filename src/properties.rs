@@ -0,0 +1,293 @@
+//! Java's `.properties` format, which `server.properties` is written in
+//!
+//! Lines are `key=value` (or `key: value`, or just a space between them),
+//! `#` and `!` start comments, a backslash at the end of a line continues it
+//! on the next one, and anything outside of printable ASCII is `\uXXXX`.
+//! Entries keep the order they were read in so the file can be written back
+//! without shuffling it around.
+use std::fmt::{self, Write};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Properties {
+    entries: Vec<(String, String)>,
+}
+
+impl Properties {
+    /// Parse the way `java.util.Properties::load` does, which never fails, it just does its best
+    pub fn parse(text: &str) -> Self {
+        let mut properties = Self::default();
+        let mut lines = text.lines();
+
+        while let Some(line) = lines.next() {
+            let mut line = line.trim_start().to_owned();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+
+            while ends_with_continuation(&line) {
+                line.pop();
+                match lines.next() {
+                    Some(next) => line.push_str(next.trim_start()),
+                    None => break,
+                }
+            }
+
+            let (key, value) = split_entry(&line);
+            properties.set(&unescape(key), unescape(value));
+        }
+
+        properties
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(entry, _)| entry == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Replace the value of `key`, or add it at the end if it's new
+    pub fn set(&mut self, key: &str, value: String) {
+        if let Some((_, existing)) = self.entries.iter_mut().find(|(entry, _)| entry == key) {
+            *existing = value;
+        } else {
+            self.entries.push((key.to_owned(), value));
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(key, _)| key.as_str())
+    }
+}
+
+impl fmt::Display for Properties {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.entries {
+            writeln!(f, "{}={}", escape(key, true), escape(value, false))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An odd number of backslashes at the end, an even number is just escaped backslashes
+fn ends_with_continuation(line: &str) -> bool {
+    line.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1
+}
+
+/// Split at the first unescaped `=`, `:` or whitespace, along with whatever padding is around it
+fn split_entry(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    let mut end = line.len();
+
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '=' || c == ':' || c.is_whitespace() {
+            end = i;
+            break;
+        }
+    }
+
+    let key = &line[..end];
+    let rest = line[end..].trim_start();
+    let rest = rest.strip_prefix(['=', ':']).map_or(rest, str::trim_start);
+
+    (key, rest)
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut units = vec![];
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            flush_units(&mut units, &mut unescaped);
+            unescaped.push(c);
+            continue;
+        }
+
+        let escaped = chars.next();
+
+        // UTF-16 units, which may be half of a surrogate pair that needs the next one
+        if escaped == Some('u') {
+            let hex: String = chars.by_ref().take(4).collect();
+            if let Ok(unit) = u16::from_str_radix(&hex, 16) {
+                units.push(unit);
+                continue;
+            }
+            flush_units(&mut units, &mut unescaped);
+            unescaped.push('u');
+            unescaped.push_str(&hex);
+            continue;
+        }
+
+        flush_units(&mut units, &mut unescaped);
+        match escaped {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('f') => unescaped.push('\u{c}'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    flush_units(&mut units, &mut unescaped);
+
+    unescaped
+}
+
+fn flush_units(units: &mut Vec<u16>, into: &mut String) {
+    if !units.is_empty() {
+        into.extend(char::decode_utf16(units.drain(..)).map(|c| c.unwrap_or('\u{fffd}')));
+    }
+}
+
+/// Escape the way `Properties::store` does, so vanilla reads back exactly what was written
+fn escape(text: &str, is_key: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for (i, c) in text.chars().enumerate() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\u{c}' => escaped.push_str("\\f"),
+            '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            // spaces only matter in keys, or leading ones in values
+            ' ' if is_key || i == 0 => escaped.push_str("\\ "),
+            ' '..='~' => escaped.push(c),
+            _ => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    let _ = write!(escaped, "\\u{unit:04X}");
+                }
+            }
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(properties: &Properties) -> Vec<(&str, &str)> {
+        properties
+            .entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn separators_and_comments() {
+        let properties = Properties::parse(
+            "#Minecraft server properties\n\
+             ! also a comment\n\
+             \n\
+             equals=1\n\
+             colon: 2\n\
+             space 3\n\
+             \x20  padded  =  4 \n\
+             empty=\n\
+             bare\n\
+             first=a=b\n",
+        );
+
+        assert_eq!(
+            entries(&properties),
+            [
+                ("equals", "1"),
+                ("colon", "2"),
+                ("space", "3"),
+                // trailing whitespace is part of the value, like in Java
+                ("padded", "4 "),
+                ("empty", ""),
+                ("bare", ""),
+                ("first", "a=b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn continuations() {
+        let properties = Properties::parse(
+            "motd=A Minecraft \\\n    Server\n\
+             two=a\\\n b\\\n  c\n\
+             path=C:\\\\\n\
+             next=value\n\
+             last=cut off\\",
+        );
+
+        assert_eq!(
+            entries(&properties),
+            [
+                ("motd", "A Minecraft Server"),
+                ("two", "abc"),
+                // an escaped backslash at the end doesn't continue anything
+                ("path", "C:\\"),
+                ("next", "value"),
+                ("last", "cut off"),
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        let properties = Properties::parse(
+            "tabs=a\\tb\\nc\\rd\\fe\n\
+             section=\\u00a7aGreen\n\
+             emoji=\\uD83D\\uDE00\n\
+             lone=\\uD83Dx\n\
+             not_hex=\\uZZZZ\n\
+             other=\\q\\\\\n\
+             key\\ with\\:separators\\=in\\ it=value\n",
+        );
+
+        assert_eq!(properties.get("tabs"), Some("a\tb\nc\rd\u{c}e"));
+        assert_eq!(properties.get("section"), Some("\u{a7}aGreen"));
+        assert_eq!(properties.get("emoji"), Some("\u{1F600}"));
+        assert_eq!(properties.get("lone"), Some("\u{fffd}x"));
+        assert_eq!(properties.get("not_hex"), Some("uZZZZ"));
+        assert_eq!(properties.get("other"), Some("q\\"));
+        assert_eq!(properties.get("key with:separators=in it"), Some("value"));
+    }
+
+    #[test]
+    fn display() {
+        let mut properties = Properties::default();
+        properties.set("motd", "\u{a7}aHello # world!".to_owned());
+        properties.set("key with space", " leading space".to_owned());
+        properties.set("multi", "line\none\\two".to_owned());
+        properties.set("emoji", "\u{1F600}".to_owned());
+
+        // what Properties::store would write
+        assert_eq!(
+            properties.to_string(),
+            "motd=\\u00A7aHello \\# world\\!\n\
+             key\\ with\\ space=\\ leading space\n\
+             multi=line\\none\\\\two\n\
+             emoji=\\uD83D\\uDE00\n"
+        );
+        assert_eq!(Properties::parse(&properties.to_string()), properties);
+    }
+
+    #[test]
+    fn set_keeps_the_order() {
+        let mut properties = Properties::parse("a=1\nb=2\na=3\n");
+        assert_eq!(entries(&properties), [("a", "3"), ("b", "2")]);
+
+        properties.set("b", "4".to_owned());
+        properties.set("c", "5".to_owned());
+        assert_eq!(properties.keys().collect::<Vec<_>>(), ["a", "b", "c"]);
+        assert_eq!(properties.get("b"), Some("4"));
+    }
+}
@@ -0,0 +1,283 @@
+//! HAProxy's PROXY protocol, for when we're behind a TCP load balancer
+//!
+//! The balancer opens the connection, so the socket's peer is always the
+//! balancer itself. With the PROXY protocol it sends a header before anything
+//! else saying where the connection really came from, either as a line of
+//! text (v1) or as a binary block (v2).
+//!
+//! Only the bytes of the header are read, so whatever comes after is still
+//! in the stream for the packet codec.
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::trace;
+
+use crate::ProtocolError;
+
+/// What every v2 header starts with
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// A v1 header, including the `\r\n`, is never longer than this
+const V1_MAX_LENGTH: usize = 107;
+
+/// Read the PROXY header at the start of `reader`
+///
+/// Gives back the source address of the proxied connection, or `None` when
+/// the proxy says the connection is its own (v1 `UNKNOWN`, v2 `LOCAL`, or an
+/// address family that isn't TCP/UDP over IP).
+pub async fn read_header<R>(reader: &mut R) -> Result<Option<SocketAddr>, ProtocolError>
+where
+    R: AsyncRead + Unpin,
+{
+    // both versions have at least this much, so it's safe to read before knowing which one it is
+    let mut start = [0; V2_SIGNATURE.len()];
+    reader.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        read_v2(reader).await
+    } else if start.starts_with(b"PROXY ") {
+        read_v1(reader, &start).await
+    } else {
+        trace!("Not a PROXY header: {start:?}");
+        Err(ProtocolError::ProxyProtocol)
+    }
+}
+
+/// `PROXY TCP4 <src> <dst> <src port> <dst port>\r\n`
+async fn read_v1<R>(reader: &mut R, start: &[u8]) -> Result<Option<SocketAddr>, ProtocolError>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut line = start.to_vec();
+
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(ProtocolError::ProxyProtocol);
+        }
+
+        line.push(reader.read_u8().await?);
+    }
+
+    let line =
+        std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| ProtocolError::ProxyProtocol)?;
+    trace!("PROXY v1 header: {line:?}");
+
+    let fields: Vec<&str> = line.split(' ').collect();
+    match fields[..] {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", source, _destination, source_port, _destination_port] => {
+            let ip: IpAddr = source.parse().map_err(|_| ProtocolError::ProxyProtocol)?;
+            let port: u16 = source_port
+                .parse()
+                .map_err(|_| ProtocolError::ProxyProtocol)?;
+
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(ProtocolError::ProxyProtocol),
+    }
+}
+
+/// The 12 byte signature, then version/command, family/protocol, length and the addresses
+async fn read_v2<R>(reader: &mut R) -> Result<Option<SocketAddr>, ProtocolError>
+where
+    R: AsyncRead + Unpin,
+{
+    let version_command = reader.read_u8().await?;
+    let family_protocol = reader.read_u8().await?;
+    let length = usize::from(reader.read_u16().await?);
+
+    // the length also covers any TLVs after the addresses, those are read and ignored
+    let mut addresses = vec![0; length];
+    reader.read_exact(&mut addresses).await?;
+    trace!("PROXY v2 header: {version_command:#x} {family_protocol:#x} {addresses:?}");
+
+    if version_command >> 4 != 2 {
+        return Err(ProtocolError::ProxyProtocol);
+    }
+
+    match version_command & 0x0F {
+        // LOCAL, the proxy talking for itself (health checks and such)
+        0x0 => return Ok(None),
+        // PROXY
+        0x1 => {}
+        _ => return Err(ProtocolError::ProxyProtocol),
+    }
+
+    match family_protocol >> 4 {
+        // AF_INET
+        0x1 => {
+            let Some(address) = addresses.get(..12) else {
+                return Err(ProtocolError::ProxyProtocol);
+            };
+            let ip: [u8; 4] = address[..4]
+                .try_into()
+                .map_err(|_| ProtocolError::ProxyProtocol)?;
+            let port = u16::from_be_bytes([address[8], address[9]]);
+
+            Ok(Some(SocketAddr::new(Ipv4Addr::from(ip).into(), port)))
+        }
+        // AF_INET6
+        0x2 => {
+            let Some(address) = addresses.get(..36) else {
+                return Err(ProtocolError::ProxyProtocol);
+            };
+            let ip: [u8; 16] = address[..16]
+                .try_into()
+                .map_err(|_| ProtocolError::ProxyProtocol)?;
+            let port = u16::from_be_bytes([address[32], address[33]]);
+
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+        }
+        // AF_UNSPEC or AF_UNIX, neither of which tell us anything useful
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What a v2 header for `command` and `family_protocol` looks like, with `addresses` after it
+    fn v2_header(command: u8, family_protocol: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend_from_slice(&[0x20 | command, family_protocol]);
+        header.extend_from_slice(&u16::try_from(addresses.len()).unwrap().to_be_bytes());
+        header.extend_from_slice(addresses);
+        header
+    }
+
+    #[tokio::test]
+    async fn v1() {
+        let mut reader = &b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 25565\r\n\x10\x00"[..];
+        assert_eq!(
+            read_header(&mut reader).await.unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        // the handshake behind it is left alone
+        assert_eq!(reader, b"\x10\x00");
+
+        let mut reader = &b"PROXY TCP6 2001:db8::1 2001:db8::2 56324 25565\r\n"[..];
+        assert_eq!(
+            read_header(&mut reader).await.unwrap(),
+            Some("[2001:db8::1]:56324".parse().unwrap())
+        );
+
+        let mut reader = &b"PROXY UNKNOWN\r\n"[..];
+        assert_eq!(read_header(&mut reader).await.unwrap(), None);
+
+        // the longest a real header gets
+        let longest = "PROXY TCP6 ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff 65535 65535\r\n";
+        assert!(read_header(&mut longest.as_bytes())
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn v1_invalid() {
+        for header in [
+            // no end in sight
+            &[b"PROXY UNKNOWN ".as_slice(), &[b'a'; V1_MAX_LENGTH]].concat()[..],
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 56324\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.1 65536 25565\r\n",
+            b"PROXY TCP4 example.com 198.51.100.1 56324 25565\r\n",
+            b"PROXY UDP4 192.0.2.1 198.51.100.1 56324 25565\r\n",
+            // a handshake, from someone who skipped the proxy
+            b"\x10\x00\xfb\x05\x09localhost\x63\xdd\x01",
+        ] {
+            assert!(matches!(
+                read_header(&mut &header[..]).await,
+                Err(ProtocolError::ProxyProtocol)
+            ));
+        }
+
+        // cut off before the end of the line
+        let mut reader = &b"PROXY TCP4 192.0.2.1 198.51.100.1"[..];
+        assert!(matches!(
+            read_header(&mut reader).await,
+            Err(ProtocolError::IOError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn v2() {
+        let mut ipv4 = v2_header(
+            0x1,
+            0x11,
+            &[192, 0, 2, 1, 198, 51, 100, 1, 0xDC, 0x04, 0x63, 0xDD],
+        );
+        ipv4.extend_from_slice(b"\x10\x00");
+        let mut reader = &ipv4[..];
+        assert_eq!(
+            read_header(&mut reader).await.unwrap(),
+            Some("192.0.2.1:56324".parse().unwrap())
+        );
+        assert_eq!(reader, b"\x10\x00");
+
+        let mut addresses = vec![0; 36];
+        addresses[..16].copy_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        addresses[32..34].copy_from_slice(&56324_u16.to_be_bytes());
+        let ipv6 = v2_header(0x1, 0x21, &addresses);
+        assert_eq!(
+            read_header(&mut &ipv6[..]).await.unwrap(),
+            Some("[2001:db8::1]:56324".parse().unwrap())
+        );
+
+        // TLVs after the addresses are skipped over
+        let mut with_tlvs = vec![192, 0, 2, 1, 198, 51, 100, 1, 0xDC, 0x04, 0x63, 0xDD];
+        with_tlvs.extend_from_slice(&[0x04, 0x00, 0x02, 0xAB, 0xCD]);
+        let mut header = v2_header(0x1, 0x11, &with_tlvs);
+        header.push(0x10);
+        let mut reader = &header[..];
+        assert!(read_header(&mut reader).await.unwrap().is_some());
+        assert_eq!(reader, [0x10]);
+
+        let local = v2_header(0x0, 0x00, &[]);
+        assert_eq!(read_header(&mut &local[..]).await.unwrap(), None);
+
+        let unix = v2_header(0x1, 0x31, &[0; 216]);
+        assert_eq!(read_header(&mut &unix[..]).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn v2_invalid() {
+        // the addresses don't fit in the length it gave
+        let short = v2_header(0x1, 0x11, &[192, 0, 2, 1, 198, 51, 100, 1]);
+        assert!(matches!(
+            read_header(&mut &short[..]).await,
+            Err(ProtocolError::ProxyProtocol)
+        ));
+
+        let short = v2_header(0x1, 0x21, &[0; 12]);
+        assert!(matches!(
+            read_header(&mut &short[..]).await,
+            Err(ProtocolError::ProxyProtocol)
+        ));
+
+        let mut version_1 = v2_header(0x1, 0x11, &[0; 12]);
+        version_1[12] = 0x11;
+        assert!(matches!(
+            read_header(&mut &version_1[..]).await,
+            Err(ProtocolError::ProxyProtocol)
+        ));
+
+        let unknown_command = v2_header(0x2, 0x11, &[0; 12]);
+        assert!(matches!(
+            read_header(&mut &unknown_command[..]).await,
+            Err(ProtocolError::ProxyProtocol)
+        ));
+
+        // the length says there's more than what's there
+        let mut truncated = v2_header(0x1, 0x11, &[0; 12]);
+        truncated.truncate(20);
+        assert!(matches!(
+            read_header(&mut &truncated[..]).await,
+            Err(ProtocolError::IOError(_))
+        ));
+
+        assert!(matches!(
+            read_header(&mut &V2_SIGNATURE[..8]).await,
+            Err(ProtocolError::IOError(_))
+        ));
+    }
+}
@@ -0,0 +1,451 @@
+//! The UDP query protocol (GameSpy4), for server lists and monitoring tools
+//!
+//! A client first does a handshake to get a challenge token, then asks for
+//! either the basic stat (MOTD, player counts, address) or the full stat,
+//! which adds the version, plugins and the names of everyone online.
+//! Tokens are tied to the address that asked for them and expire, so the
+//! responses (which are much bigger than the requests) can't be bounced off
+//! us at a spoofed address.
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use tokio::net::UdpSocket;
+use tracing::{debug, error, info, trace};
+
+use crate::{server::ServerHandle, ProtocolError};
+
+/// What every request starts with
+const MAGIC: [u8; 2] = [0xFE, 0xFD];
+
+const TYPE_STAT: u8 = 0x00;
+const TYPE_HANDSHAKE: u8 = 0x09;
+
+/// Session ids are only kept to the bits the protocol says matter
+const SESSION_ID_MASK: i32 = 0x0F0F_0F0F;
+
+/// How long a challenge token can be used after the handshake, same as vanilla
+const CHALLENGE_LIFETIME: Duration = Duration::from_secs(30);
+
+/// How many addresses can be waiting on a challenge at once, any more is someone spoofing them
+const MAX_CHALLENGES: usize = 10_000;
+
+/// Nothing a client sends is anywhere near this big
+const MAX_REQUEST_LENGTH: usize = 1460;
+
+/// Constant padding the full stat response starts with, which clients skip over
+const FULL_STAT_PADDING: &[u8] = b"splitnum\0\x80\0";
+const PLAYER_SECTION_PADDING: &[u8] = b"\x01player_\0\0";
+
+const GAME_TYPE: &str = "SMP";
+const GAME_ID: &str = "MINECRAFT";
+const MAP: &str = "world";
+
+#[derive(Debug)]
+struct Challenge {
+    token: i32,
+    issued_at: Instant,
+}
+
+#[derive(Debug)]
+enum Request {
+    Handshake { session_id: i32 },
+    BasicStat { session_id: i32, token: i32 },
+    FullStat { session_id: i32, token: i32 },
+}
+
+impl Request {
+    fn read_from<R: Read>(reader: &mut R) -> Result<Self, ProtocolError> {
+        let mut magic = [0; 2];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(ProtocolError::Malformed);
+        }
+
+        let kind = reader.read_u8()?;
+        let session_id = reader.read_i32::<BigEndian>()? & SESSION_ID_MASK;
+
+        match kind {
+            TYPE_HANDSHAKE => Ok(Self::Handshake { session_id }),
+            TYPE_STAT => {
+                let token = reader.read_i32::<BigEndian>()?;
+
+                // the full stat request is the basic one with 4 bytes of padding after it
+                let mut padding = vec![];
+                reader.read_to_end(&mut padding)?;
+
+                if padding.is_empty() {
+                    Ok(Self::BasicStat { session_id, token })
+                } else {
+                    Ok(Self::FullStat { session_id, token })
+                }
+            }
+            _ => Err(ProtocolError::Malformed),
+        }
+    }
+}
+
+pub struct QueryServer {
+    socket: UdpSocket,
+    server: ServerHandle,
+    /// The address of the game's TCP listener, which is what gets reported
+    host: SocketAddr,
+    challenges: HashMap<SocketAddr, Challenge>,
+    /// When expired challenges were last cleared out
+    last_pruned: Instant,
+}
+
+impl QueryServer {
+    pub fn new(socket: UdpSocket, server: ServerHandle, host: SocketAddr) -> Self {
+        Self {
+            socket,
+            server,
+            host,
+            challenges: HashMap::new(),
+            last_pruned: Instant::now(),
+        }
+    }
+
+    /// Answer queries until the socket fails
+    pub async fn run(mut self) {
+        if let Ok(addr) = self.socket.local_addr() {
+            info!("Query listening on ({addr})");
+        }
+
+        let mut buffer = [0; MAX_REQUEST_LENGTH];
+
+        loop {
+            let (length, addr) = match self.socket.recv_from(&mut buffer).await {
+                Ok(received) => received,
+                Err(e) => {
+                    error!("Query socket failed: {e:?}");
+                    return;
+                }
+            };
+            trace!("Query from ({addr}): {:?}", &buffer[..length]);
+
+            // one bad packet is no reason to stop answering everyone else
+            let response = match self.handle(&buffer[..length], addr) {
+                Ok(Some(response)) => response,
+                Ok(None) => continue,
+                Err(e) => {
+                    debug!("Bad query from ({addr}): {e:?}");
+                    continue;
+                }
+            };
+
+            if let Err(e) = self.socket.send_to(&response, addr).await {
+                debug!("Couldn't answer query from ({addr}): {e:?}");
+            }
+        }
+    }
+
+    fn handle(
+        &mut self,
+        mut packet: &[u8],
+        addr: SocketAddr,
+    ) -> Result<Option<Vec<u8>>, ProtocolError> {
+        let request = Request::read_from(&mut packet)?;
+
+        match request {
+            Request::Handshake { session_id } => {
+                let Some(token) = self.issue_challenge(addr) else {
+                    return Ok(None);
+                };
+
+                let mut response = vec![TYPE_HANDSHAKE];
+                response.write_i32::<BigEndian>(session_id)?;
+                // the token goes out as text, even though it comes back as an int
+                write_string(&mut response, &token.to_string())?;
+
+                Ok(Some(response))
+            }
+            Request::BasicStat { session_id, token } => {
+                if !self.check_challenge(addr, token) {
+                    return Ok(None);
+                }
+
+                Ok(Some(self.basic_stat(session_id)?))
+            }
+            Request::FullStat { session_id, token } => {
+                if !self.check_challenge(addr, token) {
+                    return Ok(None);
+                }
+
+                Ok(Some(self.full_stat(session_id)?))
+            }
+        }
+    }
+
+    fn issue_challenge(&mut self, addr: SocketAddr) -> Option<i32> {
+        // like vanilla, expired tokens are only cleared out every so often, not on every handshake
+        if self.last_pruned.elapsed() >= CHALLENGE_LIFETIME {
+            self.challenges
+                .retain(|_, challenge| challenge.issued_at.elapsed() < CHALLENGE_LIFETIME);
+            self.last_pruned = Instant::now();
+        }
+
+        if self.challenges.len() >= MAX_CHALLENGES && !self.challenges.contains_key(&addr) {
+            debug!("Too many query challenges out, not giving ({addr}) one");
+            return None;
+        }
+
+        let token = rand::random::<i32>() & SESSION_ID_MASK;
+        self.challenges.insert(
+            addr,
+            Challenge {
+                token,
+                issued_at: Instant::now(),
+            },
+        );
+
+        Some(token)
+    }
+
+    fn check_challenge(&self, addr: SocketAddr, token: i32) -> bool {
+        let valid = self.challenges.get(&addr).is_some_and(|challenge| {
+            challenge.token == token && challenge.issued_at.elapsed() < CHALLENGE_LIFETIME
+        });
+
+        if !valid {
+            debug!("Query from ({addr}) with a bad challenge token");
+        }
+
+        valid
+    }
+
+    fn basic_stat(&self, session_id: i32) -> Result<Vec<u8>, ProtocolError> {
+        let status = self.server.status();
+
+        let mut response = vec![TYPE_STAT];
+        response.write_i32::<BigEndian>(session_id)?;
+        write_string(&mut response, &status.description().to_plain())?;
+        write_string(&mut response, GAME_TYPE)?;
+        write_string(&mut response, MAP)?;
+        write_string(&mut response, &status.online_players().to_string())?;
+        write_string(&mut response, &status.max_players().to_string())?;
+        // the one little endian number in the whole protocol
+        response.write_u16::<LittleEndian>(self.host.port())?;
+        write_string(&mut response, &self.host.ip().to_string())?;
+
+        Ok(response)
+    }
+
+    fn full_stat(&self, session_id: i32) -> Result<Vec<u8>, ProtocolError> {
+        let status = self.server.status();
+        let players = self.server.players();
+
+        let values = [
+            ("hostname", status.description().to_plain()),
+            ("gametype", GAME_TYPE.to_owned()),
+            ("game_id", GAME_ID.to_owned()),
+            ("version", status.version_name().to_owned()),
+            ("plugins", String::new()),
+            ("map", MAP.to_owned()),
+            ("numplayers", status.online_players().to_string()),
+            ("maxplayers", status.max_players().to_string()),
+            ("hostport", self.host.port().to_string()),
+            ("hostip", self.host.ip().to_string()),
+        ];
+
+        let mut response = vec![TYPE_STAT];
+        response.write_i32::<BigEndian>(session_id)?;
+        response.write_all(FULL_STAT_PADDING)?;
+
+        for (key, value) in values {
+            write_string(&mut response, key)?;
+            write_string(&mut response, &value)?;
+        }
+        // an empty key ends the section
+        response.write_u8(0)?;
+
+        response.write_all(PLAYER_SECTION_PADDING)?;
+        for player in players {
+            write_string(&mut response, &player.name)?;
+        }
+        response.write_u8(0)?;
+
+        Ok(response)
+    }
+}
+
+/// Query strings are null terminated, not length prefixed like everywhere else
+fn write_string<W: Write>(writer: &mut W, string: &str) -> Result<usize, ProtocolError> {
+    // vanilla sends them as ISO-8859-1, so anything outside of it becomes a `?`
+    let bytes: Vec<u8> = string
+        .chars()
+        .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+        .collect();
+
+    writer.write_all(&bytes)?;
+    writer.write_u8(0)?;
+
+    Ok(bytes.len() + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, sync::Arc};
+
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{auth::GameProfile, config::ServerConfig, server::Server};
+
+    async fn query_server(config: ServerConfig) -> QueryServer {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+        let server = Arc::new(Server::new(config).unwrap());
+
+        QueryServer::new(
+            socket,
+            server,
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 25565)),
+        )
+    }
+
+    fn handshake(query: &mut QueryServer, addr: SocketAddr) -> Option<Vec<u8>> {
+        query
+            .handle(&[0xFE, 0xFD, TYPE_HANDSHAKE, 0, 0, 0, 1], addr)
+            .unwrap()
+    }
+
+    /// The challenge token a handshake from `addr` gets
+    fn token(query: &mut QueryServer, addr: SocketAddr) -> i32 {
+        let response = handshake(query, addr).unwrap();
+        std::str::from_utf8(&response[5..response.len() - 1])
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    /// Ask for the basic stat, or the full one with `padding`
+    fn stat(query: &mut QueryServer, padding: &[u8]) -> Vec<u8> {
+        let client = addr(0);
+        let token = token(query, client);
+
+        let mut request = vec![0xFE, 0xFD, TYPE_STAT, 0, 0, 0, 1];
+        request.extend_from_slice(&token.to_be_bytes());
+        request.extend_from_slice(padding);
+        query.handle(&request, client).unwrap().unwrap()
+    }
+
+    /// A server whose MOTD has something ISO-8859-1 can and can't show, with Notch online
+    async fn stat_server() -> QueryServer {
+        let query = query_server(ServerConfig {
+            motd: "\u{a7}aCaf\u{e9} \u{2603}".to_owned(),
+            ..ServerConfig::default()
+        })
+        .await;
+
+        let peer = addr(1);
+        query.server.register(peer);
+        query.server.set_profile(
+            &peer,
+            GameProfile {
+                uuid: Uuid::nil(),
+                name: "Notch".to_owned(),
+                properties: vec![],
+            },
+        );
+
+        query
+    }
+
+    fn addr(n: usize) -> SocketAddr {
+        let n = u32::try_from(n).unwrap();
+        SocketAddr::from((Ipv4Addr::from(0x0A00_0000 + n), 25565))
+    }
+
+    #[tokio::test]
+    async fn challenges_are_capped() {
+        let mut query = query_server(ServerConfig::default()).await;
+
+        for n in 0..MAX_CHALLENGES {
+            assert!(handshake(&mut query, addr(n)).is_some());
+        }
+        assert_eq!(query.challenges.len(), MAX_CHALLENGES);
+
+        // nobody new gets one, but whoever already has one can get another
+        assert!(handshake(&mut query, addr(MAX_CHALLENGES)).is_none());
+        assert!(handshake(&mut query, addr(0)).is_some());
+        assert_eq!(query.challenges.len(), MAX_CHALLENGES);
+
+        // once they expire they're cleared out again
+        let expired = Instant::now().checked_sub(CHALLENGE_LIFETIME).unwrap();
+        for challenge in query.challenges.values_mut() {
+            challenge.issued_at = expired;
+        }
+        query.last_pruned = expired;
+
+        assert!(handshake(&mut query, addr(MAX_CHALLENGES)).is_some());
+        assert_eq!(query.challenges.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stat_needs_the_token() {
+        let mut query = query_server(ServerConfig::default()).await;
+        let client = addr(0);
+
+        let token = token(&mut query, client);
+
+        let mut request = vec![0xFE, 0xFD, TYPE_STAT, 0, 0, 0, 1];
+        request.extend_from_slice(&token.to_be_bytes());
+        assert!(query.handle(&request, client).unwrap().is_some());
+
+        // not from anywhere else, and not with another token
+        assert!(query.handle(&request, addr(1)).unwrap().is_none());
+        request[7..11].copy_from_slice(&(token ^ 1).to_be_bytes());
+        assert!(query.handle(&request, client).unwrap().is_none());
+    }
+
+    #[test]
+    fn strings() {
+        let mut written = vec![];
+        assert_eq!(
+            write_string(&mut written, "Caf\u{e9} \u{2603}!").unwrap(),
+            8
+        );
+        assert_eq!(written, b"Caf\xe9 ?!\0");
+    }
+
+    #[tokio::test]
+    async fn basic_stat() {
+        let mut query = stat_server().await;
+
+        let mut expected = vec![TYPE_STAT, 0, 0, 0, 1];
+        expected.extend_from_slice(b"Caf\xe9 ?\0SMP\0world\0");
+        expected.extend_from_slice(b"1\x0020\0");
+        // 25565, little endian
+        expected.extend_from_slice(&[0xDD, 0x63]);
+        expected.extend_from_slice(b"127.0.0.1\0");
+
+        assert_eq!(stat(&mut query, &[]), expected);
+    }
+
+    #[tokio::test]
+    async fn full_stat() {
+        let mut query = stat_server().await;
+
+        let mut expected = vec![TYPE_STAT, 0, 0, 0, 1];
+        expected.extend_from_slice(b"splitnum\0\x80\0");
+        expected.extend_from_slice(b"hostname\0Caf\xe9 ?\0");
+        expected.extend_from_slice(b"gametype\0SMP\0");
+        expected.extend_from_slice(b"game_id\0MINECRAFT\0");
+        expected.extend_from_slice(b"version\0");
+        expected.extend_from_slice(crate::VERSION_NAME.as_bytes());
+        expected.extend_from_slice(b"\0plugins\0\0");
+        expected.extend_from_slice(b"map\0world\0");
+        expected.extend_from_slice(b"numplayers\x001\0maxplayers\x0020\0");
+        expected.extend_from_slice(b"hostport\x0025565\0hostip\x00127.0.0.1\0");
+        expected.push(0);
+        expected.extend_from_slice(b"\x01player_\0\0");
+        expected.extend_from_slice(b"Notch\0");
+        expected.push(0);
+
+        assert_eq!(stat(&mut query, &[0, 0, 0, 0]), expected);
+    }
+}
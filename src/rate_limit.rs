@@ -0,0 +1,168 @@
+//! Per-IP token buckets, to keep anyone from hammering the cheap-looking but not free endpoints
+//!
+//! Every address starts with a full bucket of `burst` tokens and gets one back
+//! every `refill`. Each request takes a token, and without one it's refused.
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How many addresses are tracked before the ones with full buckets get forgotten
+const PRUNE_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// How many requests can be made back to back
+    pub burst: u32,
+    /// How long it takes to earn one more request
+    pub refill: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Take a token for `ip`, `false` means it has none left
+    pub fn try_acquire(&self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        let full = f64::from(self.limit.burst);
+
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        if buckets.len() >= PRUNE_THRESHOLD {
+            // a full bucket is the same as never having seen the address
+            buckets.retain(|_, bucket| self.refilled(*bucket, now) < full);
+        }
+
+        let bucket = buckets.entry(ip).or_insert_with(|| Bucket {
+            tokens: full,
+            updated: now,
+        });
+        let tokens = self.refilled(*bucket, now);
+        let allowed = tokens >= 1.0;
+
+        *bucket = Bucket {
+            tokens: if allowed { tokens - 1.0 } else { tokens },
+            updated: now,
+        };
+        drop(buckets);
+
+        allowed
+    }
+
+    fn refilled(&self, bucket: Bucket, now: Instant) -> f64 {
+        let earned = now.duration_since(bucket.updated).as_secs_f64()
+            / self.limit.refill.as_secs_f64().max(f64::EPSILON);
+
+        (bucket.tokens + earned).min(f64::from(self.limit.burst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: RateLimit = RateLimit {
+        burst: 3,
+        refill: Duration::from_secs(10),
+    };
+
+    fn ip(n: u32) -> IpAddr {
+        IpAddr::from(n.to_be_bytes())
+    }
+
+    /// Pretend `ip` last made a request `ago`, instead of sleeping through the refill
+    fn rewind(limiter: &RateLimiter, ip: IpAddr, ago: Duration) {
+        let mut buckets = limiter.buckets.lock().unwrap();
+        let bucket = buckets.get_mut(&ip).unwrap();
+        bucket.updated = bucket.updated.checked_sub(ago).unwrap();
+        drop(buckets);
+    }
+
+    #[test]
+    fn burst() {
+        let limiter = RateLimiter::new(LIMIT);
+
+        for _ in 0..LIMIT.burst {
+            assert!(limiter.try_acquire(ip(1)));
+        }
+        assert!(!limiter.try_acquire(ip(1)));
+        assert!(!limiter.try_acquire(ip(1)));
+
+        // everyone has their own bucket
+        assert!(limiter.try_acquire(ip(2)));
+    }
+
+    #[test]
+    fn refill() {
+        let limiter = RateLimiter::new(LIMIT);
+        for _ in 0..LIMIT.burst {
+            assert!(limiter.try_acquire(ip(1)));
+        }
+
+        // not quite a whole token yet
+        rewind(&limiter, ip(1), LIMIT.refill / 2);
+        assert!(!limiter.try_acquire(ip(1)));
+
+        // the half from before still counts
+        rewind(&limiter, ip(1), LIMIT.refill / 2);
+        assert!(limiter.try_acquire(ip(1)));
+        assert!(!limiter.try_acquire(ip(1)));
+
+        // waiting longer doesn't give more than a full burst
+        rewind(&limiter, ip(1), LIMIT.refill * 100);
+        for _ in 0..LIMIT.burst {
+            assert!(limiter.try_acquire(ip(1)));
+        }
+        assert!(!limiter.try_acquire(ip(1)));
+    }
+
+    #[test]
+    fn prune() {
+        let limiter = RateLimiter::new(LIMIT);
+        let threshold = u32::try_from(PRUNE_THRESHOLD).unwrap();
+
+        for n in 0..threshold {
+            assert!(limiter.try_acquire(ip(n)));
+        }
+        assert_eq!(limiter.buckets.lock().unwrap().len(), PRUNE_THRESHOLD);
+
+        // half of them have been quiet long enough to be full again
+        for n in (0..threshold).step_by(2) {
+            rewind(&limiter, ip(n), LIMIT.refill);
+        }
+        assert!(limiter.try_acquire(ip(threshold)));
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), PRUNE_THRESHOLD / 2 + 1);
+        assert!(!buckets.contains_key(&ip(0)));
+        assert!(buckets.contains_key(&ip(1)));
+        drop(buckets);
+
+        // forgetting them didn't cost anyone requests
+        for _ in 0..LIMIT.burst {
+            assert!(limiter.try_acquire(ip(0)));
+        }
+    }
+}
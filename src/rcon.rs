@@ -0,0 +1,510 @@
+//! Source RCON, for running commands remotely
+//!
+//! Every packet is a little endian length, request id and type, then a null
+//! terminated body and one more null. A client logs in with the password,
+//! then sends commands and gets their output back under the same request id,
+//! split over as many packets as it takes.
+//!
+//! Addresses that keep getting the password wrong are locked out for a while,
+//! so guessing it over the network isn't an option.
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, error, info, trace, warn};
+
+use crate::{command, server::ServerHandle, ProtocolError};
+
+const TYPE_RESPONSE: i32 = 0;
+/// Also the type of the login response, the id is what tells success from failure
+const TYPE_COMMAND: i32 = 2;
+const TYPE_LOGIN: i32 = 3;
+
+/// The request id a failed login is answered with
+const AUTH_FAILED_ID: i32 = -1;
+
+/// Id, type and the two nulls, so the smallest packet there is
+const MIN_PACKET_LENGTH: usize = 10;
+/// Same limit vanilla puts on what it accepts
+const MAX_PACKET_LENGTH: usize = 1460;
+/// Longest body in one response packet, longer output is split
+const MAX_RESPONSE_BODY: usize = 4096;
+
+/// How many wrong passwords before an address gets locked out
+const MAX_AUTH_FAILURES: u32 = 3;
+/// How long a lockout lasts, which is also how long failures are remembered
+const LOCKOUT: Duration = Duration::from_mins(5);
+
+#[derive(Debug)]
+struct Packet {
+    id: i32,
+    kind: i32,
+    body: String,
+}
+
+impl Packet {
+    /// `None` when the client closed the connection between packets
+    async fn read_from<R>(reader: &mut R) -> Result<Option<Self>, ProtocolError>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let length = match reader.read_i32_le().await {
+            Ok(length) => usize::try_from(length)?,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        if !(MIN_PACKET_LENGTH..=MAX_PACKET_LENGTH).contains(&length) {
+            return Err(ProtocolError::FrameTooLarge(length));
+        }
+
+        let id = reader.read_i32_le().await?;
+        let kind = reader.read_i32_le().await?;
+
+        let mut body = vec![0; length - 8];
+        reader.read_exact(&mut body).await?;
+        if !body.ends_with(&[0, 0]) {
+            return Err(ProtocolError::Malformed);
+        }
+        body.truncate(body.len() - 2);
+
+        Ok(Some(Self {
+            id,
+            kind,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        }))
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, ProtocolError> {
+        let length = i32::try_from(self.body.len() + MIN_PACKET_LENGTH)?;
+
+        let mut packet = Vec::with_capacity(self.body.len() + 14);
+        packet.extend_from_slice(&length.to_le_bytes());
+        packet.extend_from_slice(&self.id.to_le_bytes());
+        packet.extend_from_slice(&self.kind.to_le_bytes());
+        packet.extend_from_slice(self.body.as_bytes());
+        packet.extend_from_slice(&[0, 0]);
+
+        Ok(packet)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+/// Who got the password wrong, and how often
+#[derive(Debug, Default)]
+struct Lockouts {
+    failures: Mutex<HashMap<IpAddr, Failures>>,
+}
+
+impl Lockouts {
+    fn is_locked_out(&self, ip: IpAddr) -> bool {
+        let mut failures = self.failures();
+        failures.retain(|_, failure| failure.last.elapsed() < LOCKOUT);
+
+        failures
+            .get(&ip)
+            .is_some_and(|failure| failure.count >= MAX_AUTH_FAILURES)
+    }
+
+    fn record_failure(&self, ip: IpAddr) {
+        let now = Instant::now();
+
+        self.failures()
+            .entry(ip)
+            .and_modify(|failure| {
+                failure.count += 1;
+                failure.last = now;
+            })
+            .or_insert(Failures {
+                count: 1,
+                last: now,
+            });
+    }
+
+    fn clear(&self, ip: IpAddr) {
+        self.failures().remove(&ip);
+    }
+
+    fn failures(&self) -> std::sync::MutexGuard<'_, HashMap<IpAddr, Failures>> {
+        self.failures
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+pub struct RconServer {
+    listener: TcpListener,
+    server: ServerHandle,
+    password: Arc<str>,
+    lockouts: Arc<Lockouts>,
+}
+
+impl RconServer {
+    pub fn new(listener: TcpListener, server: ServerHandle, password: &str) -> Self {
+        Self {
+            listener,
+            server,
+            password: password.into(),
+            lockouts: Arc::default(),
+        }
+    }
+
+    /// Accept RCON connections until the listener fails
+    pub async fn run(self) {
+        if let Ok(addr) = self.listener.local_addr() {
+            info!("RCON listening on ({addr})");
+        }
+
+        loop {
+            let (stream, addr) = match self.listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    error!("RCON listener failed: {e:?}");
+                    return;
+                }
+            };
+
+            if self.lockouts.is_locked_out(addr.ip()) {
+                debug!("RCON ({addr}) is locked out, closing");
+                continue;
+            }
+
+            info!("RCON ({addr}) has connected.");
+            let connection = RconConnection {
+                stream,
+                addr,
+                authenticated: false,
+                server: Arc::clone(&self.server),
+                password: Arc::clone(&self.password),
+                lockouts: Arc::clone(&self.lockouts),
+            };
+
+            tokio::spawn(async move {
+                if let Err(e) = connection.handle().await {
+                    debug!("RCON ({addr}) errored: {e:?}");
+                }
+                info!("RCON ({addr}) has disconnected.");
+            });
+        }
+    }
+}
+
+struct RconConnection {
+    stream: TcpStream,
+    addr: SocketAddr,
+    authenticated: bool,
+    server: ServerHandle,
+    password: Arc<str>,
+    lockouts: Arc<Lockouts>,
+}
+
+impl RconConnection {
+    async fn handle(mut self) -> Result<(), ProtocolError> {
+        while let Some(packet) = Packet::read_from(&mut self.stream).await? {
+            trace!("RCON ({}) packet: {packet:?}", self.addr);
+
+            match packet.kind {
+                TYPE_LOGIN => {
+                    if !self.login(&packet).await? {
+                        return Ok(());
+                    }
+                }
+                TYPE_COMMAND if self.authenticated => {
+                    info!("RCON ({}) ran {:?}", self.addr, packet.body);
+                    let output = command::execute(&self.server, &packet.body);
+                    self.respond(packet.id, &output).await?;
+                }
+                // clients send an empty response after a command and wait for it to come
+                // back, since that's the only way to tell where a split response ends
+                TYPE_RESPONSE if self.authenticated => {
+                    self.respond(packet.id, "").await?;
+                }
+                _ => {
+                    warn!(
+                        "RCON ({}) sent a type {} packet without logging in",
+                        self.addr, packet.kind
+                    );
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check the password, `false` means the connection should be closed
+    async fn login(&mut self, packet: &Packet) -> Result<bool, ProtocolError> {
+        let ip = self.addr.ip();
+
+        // connections opened before the lockout started don't get to keep guessing
+        if self.lockouts.is_locked_out(ip) {
+            debug!(
+                "RCON ({}) is locked out, not checking the password",
+                self.addr
+            );
+            self.authenticated = false;
+            self.send(&Packet {
+                id: AUTH_FAILED_ID,
+                kind: TYPE_COMMAND,
+                body: String::new(),
+            })
+            .await?;
+
+            return Ok(false);
+        }
+
+        if password_matches(&packet.body, &self.password) {
+            self.authenticated = true;
+            self.lockouts.clear(ip);
+            info!("RCON ({}) logged in", self.addr);
+
+            self.send(&Packet {
+                id: packet.id,
+                kind: TYPE_COMMAND,
+                body: String::new(),
+            })
+            .await?;
+
+            return Ok(true);
+        }
+
+        warn!("RCON ({}) used the wrong password", self.addr);
+        self.authenticated = false;
+        self.lockouts.record_failure(ip);
+
+        self.send(&Packet {
+            id: AUTH_FAILED_ID,
+            kind: TYPE_COMMAND,
+            body: String::new(),
+        })
+        .await?;
+
+        Ok(!self.lockouts.is_locked_out(ip))
+    }
+
+    /// Send `output` back, in as many packets as it needs
+    async fn respond(&mut self, id: i32, output: &str) -> Result<(), ProtocolError> {
+        let mut rest = output;
+
+        loop {
+            let mut split = rest.len().min(MAX_RESPONSE_BODY);
+            // never cut a character in half
+            while !rest.is_char_boundary(split) {
+                split -= 1;
+            }
+            let (body, remaining) = rest.split_at(split);
+
+            self.send(&Packet {
+                id,
+                kind: TYPE_RESPONSE,
+                body: body.to_owned(),
+            })
+            .await?;
+
+            if remaining.is_empty() {
+                return Ok(());
+            }
+            rest = remaining;
+        }
+    }
+
+    async fn send(&mut self, packet: &Packet) -> Result<(), ProtocolError> {
+        self.stream.write_all(&packet.encode()?).await?;
+        Ok(())
+    }
+}
+
+/// Whether `attempt` is the password, without taking longer the more of it is right
+fn password_matches(attempt: &str, password: &str) -> bool {
+    // hashing makes both the same length, and `verify_slice` doesn't stop at the first difference
+    let mac =
+        |text: &str| Hmac::<Sha256>::new_from_slice(b"rcon").map(|mac| mac.chain_update(text));
+
+    match (mac(attempt), mac(password)) {
+        (Ok(attempt), Ok(password)) => attempt
+            .verify_slice(&password.finalize().into_bytes())
+            .is_ok(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::ServerConfig, server::Server};
+
+    /// A connection over loopback, with the client's end of the socket
+    async fn open(lockouts: &Arc<Lockouts>) -> (RconConnection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, addr) = listener.accept().await.unwrap();
+
+        let connection = RconConnection {
+            stream,
+            addr,
+            authenticated: false,
+            server: Arc::new(Server::new(ServerConfig::default()).unwrap()),
+            password: "hunter2".into(),
+            lockouts: Arc::clone(lockouts),
+        };
+
+        (connection, client)
+    }
+
+    fn login(password: &str) -> Packet {
+        Packet {
+            id: 7,
+            kind: TYPE_LOGIN,
+            body: password.to_owned(),
+        }
+    }
+
+    #[test]
+    fn encode() {
+        let packet = Packet {
+            id: 1,
+            kind: TYPE_COMMAND,
+            body: "list".to_owned(),
+        };
+
+        assert_eq!(
+            packet.encode().unwrap(),
+            [14, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, b'l', b'i', b's', b't', 0, 0]
+        );
+    }
+
+    #[tokio::test]
+    async fn read_from() {
+        let bytes = [
+            14, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, b'l', b'i', b's', b't', 0, 0,
+        ];
+        let packet = Packet::read_from(&mut &bytes[..]).await.unwrap().unwrap();
+        assert_eq!(
+            (packet.id, packet.kind, packet.body.as_str()),
+            (1, 2, "list")
+        );
+
+        // nothing at all is the client hanging up
+        assert!(Packet::read_from(&mut &[][..]).await.unwrap().is_none());
+
+        let empty = [10, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let packet = Packet::read_from(&mut &empty[..]).await.unwrap().unwrap();
+        assert_eq!(packet.body, "");
+    }
+
+    #[tokio::test]
+    async fn read_from_invalid() {
+        let mut too_long = vec![];
+        too_long.extend_from_slice(&u32::try_from(MAX_PACKET_LENGTH + 1).unwrap().to_le_bytes());
+        for length in [&[9, 0, 0, 0][..], &too_long, &[0xFF, 0xFF, 0xFF, 0xFF]] {
+            let result = Packet::read_from(&mut &length[..]).await;
+            assert!(
+                matches!(
+                    result,
+                    Err(ProtocolError::FrameTooLarge(_) | ProtocolError::TryFromInt(_))
+                ),
+                "{length:?} gave {result:?}"
+            );
+        }
+
+        // only one null at the end
+        let unterminated = [10, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, b'a', 0];
+        assert!(matches!(
+            Packet::read_from(&mut &unterminated[..]).await,
+            Err(ProtocolError::Malformed)
+        ));
+
+        // shorter than it says
+        let cut_off = [14, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, b'l', b'i'];
+        assert!(matches!(
+            Packet::read_from(&mut &cut_off[..]).await,
+            Err(ProtocolError::IOError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn split_responses() {
+        let (mut connection, mut client) = open(&Arc::default()).await;
+
+        // a two byte character right where the split would be
+        let mut output = "a".repeat(MAX_RESPONSE_BODY - 1);
+        output.push('\u{e9}');
+        output.push_str("bc");
+        connection.respond(3, &output).await.unwrap();
+        drop(connection);
+
+        // responses are allowed to be bigger than what the server reads, so no `Packet::read_from`
+        let mut bytes = vec![];
+        client.read_to_end(&mut bytes).await.unwrap();
+        let mut bodies = vec![];
+        let mut rest = &bytes[..];
+        while let Some((length, packet)) = rest.split_first_chunk::<4>() {
+            let length = usize::try_from(i32::from_le_bytes(*length)).unwrap();
+            let (packet, next) = packet.split_at(length);
+            assert_eq!(packet[..8], [3, 0, 0, 0, 0, 0, 0, 0]);
+            assert_eq!(packet[length - 2..], [0, 0]);
+            bodies.push(String::from_utf8(packet[8..length - 2].to_vec()).unwrap());
+            rest = next;
+        }
+
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0].len(), MAX_RESPONSE_BODY - 1);
+        assert_eq!(bodies[1], "\u{e9}bc");
+        assert_eq!(bodies.concat(), output);
+    }
+
+    #[tokio::test]
+    async fn lockout() {
+        let lockouts = Arc::default();
+        let (mut connection, mut client) = open(&lockouts).await;
+        let ip = connection.addr.ip();
+
+        for failure in 1..=MAX_AUTH_FAILURES {
+            let keep_open = connection.login(&login("hunter3")).await.unwrap();
+            assert_eq!(keep_open, failure < MAX_AUTH_FAILURES);
+
+            let response = Packet::read_from(&mut client).await.unwrap().unwrap();
+            assert_eq!(response.id, AUTH_FAILED_ID);
+        }
+        assert!(lockouts.is_locked_out(ip));
+
+        // another connection from the same address, opened before the lockout
+        let (mut other, mut other_client) = open(&lockouts).await;
+        assert!(!other.login(&login("hunter2")).await.unwrap());
+        assert!(!other.authenticated);
+        let response = Packet::read_from(&mut other_client).await.unwrap().unwrap();
+        assert_eq!(response.id, AUTH_FAILED_ID);
+
+        // once it's over the right password works again, and clears the failures
+        lockouts.clear(ip);
+        assert!(connection.login(&login("hunter2")).await.unwrap());
+        assert!(connection.authenticated);
+        let response = Packet::read_from(&mut client).await.unwrap().unwrap();
+        assert_eq!(response.id, 7);
+    }
+
+    #[test]
+    fn passwords() {
+        assert!(password_matches("hunter2", "hunter2"));
+        assert!(!password_matches("hunter", "hunter2"));
+        assert!(!password_matches("hunter22", "hunter2"));
+        assert!(!password_matches("Hunter2", "hunter2"));
+        assert!(!password_matches("", "hunter2"));
+    }
+}
@@ -0,0 +1,314 @@
+//! Every packet copper knows about, by protocol version, state, direction and id
+//!
+//! Reading a frame looks its id up here to find the decoder, and both reading
+//! and writing use the names for logging. Unknown ids turn into
+//! [`ProtocolError::PacketId`] with the state and direction they came in on.
+//! Adding a packet is one line in the version's registration function, on top
+//! of its `#[derive(Packet)]`.
+use std::{collections::HashMap, fmt};
+
+use tracing::{error, trace};
+
+use crate::{
+    data_types::{DataType, VarInt},
+    handshaking, login,
+    packet::{self, ClientBound, Decodable, Direction, Packet, ServerBound},
+    play, status, ProtocolError, State,
+};
+
+type Decoder = Box<dyn Fn(&mut &[u8]) -> Result<ServerBound, ProtocolError> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    version: i32,
+    state: State,
+    direction: Direction,
+    id: i32,
+}
+
+struct Entry {
+    name: &'static str,
+    /// Only serverbound packets have one, we never need to read what we send
+    decoder: Option<Decoder>,
+}
+
+impl fmt::Debug for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Entry")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub struct Registry {
+    entries: HashMap<Key, Entry>,
+}
+
+impl Registry {
+    /// Every version copper speaks, with all of their packets
+    pub fn new() -> Self {
+        let mut registry = Self {
+            entries: HashMap::new(),
+        };
+
+        protocol_763(&mut registry.version(crate::PROTOCOL_VERSION));
+
+        registry
+    }
+
+    const fn version(&mut self, version: i32) -> Registration<'_> {
+        Registration {
+            registry: self,
+            version,
+        }
+    }
+
+    pub fn supports(&self, version: i32) -> bool {
+        self.entries.keys().any(|key| key.version == version)
+    }
+
+    /// The name of a packet, for logging
+    pub fn name(&self, version: i32, state: State, direction: Direction, id: i32) -> Option<&str> {
+        self.entries
+            .get(&Key {
+                version,
+                state,
+                direction,
+                id,
+            })
+            .map(|entry| entry.name)
+    }
+
+    /// Decode a whole serverbound packet, id and all
+    pub fn decode(
+        &self,
+        version: i32,
+        state: State,
+        mut frame: &[u8],
+    ) -> Result<ServerBound, ProtocolError> {
+        let VarInt(id) = VarInt::read_from(&mut frame)?;
+        let key = Key {
+            version,
+            state,
+            direction: Direction::ServerBound,
+            id,
+        };
+
+        let Some(Entry {
+            name,
+            decoder: Some(decoder),
+        }) = self.entries.get(&key)
+        else {
+            return Err(ProtocolError::PacketId {
+                state,
+                direction: Direction::ServerBound,
+                id,
+            });
+        };
+
+        trace!("Decoding {name} ({id:#04x}) in {state:?}");
+        decoder(&mut frame)
+    }
+
+    /// Encode a clientbound packet, which has to be registered for the state it's sent in
+    pub fn encode(
+        &self,
+        version: i32,
+        state: State,
+        packet: ClientBound,
+    ) -> Result<Vec<u8>, ProtocolError> {
+        let encoded = packet.encode()?;
+        let VarInt(id) = VarInt::read_from(&mut encoded.as_slice())?;
+
+        // sending a packet in the wrong state would make the client drop the connection
+        let Some(name) = self.name(version, state, Direction::ClientBound, id) else {
+            error!("Tried to send an unregistered packet {id:#04x} in {state:?}");
+            return Err(ProtocolError::PacketId {
+                state,
+                direction: Direction::ClientBound,
+                id,
+            });
+        };
+
+        trace!("Encoded {name} ({id:#04x}) in {state:?}");
+        Ok(encoded)
+    }
+}
+
+/// Adds packets to one version of the registry
+struct Registration<'a> {
+    registry: &'a mut Registry,
+    version: i32,
+}
+
+impl Registration<'_> {
+    /// A packet we read, `wrap` turns it into the `ServerBound` that the client handles
+    fn serverbound<P, F>(&mut self, name: &'static str, wrap: F)
+    where
+        P: Packet + Decodable,
+        F: Fn(P) -> ServerBound + Send + Sync + 'static,
+    {
+        debug_assert_eq!(
+            P::DIRECTION,
+            Direction::ServerBound,
+            "{name} is clientbound"
+        );
+
+        let decoder: Decoder = Box::new(move |reader| packet::decode::<P, _>(reader).map(&wrap));
+        self.insert::<P>(name, Some(decoder));
+    }
+
+    /// A packet we send
+    fn clientbound<P: Packet>(&mut self, name: &'static str) {
+        debug_assert_eq!(
+            P::DIRECTION,
+            Direction::ClientBound,
+            "{name} is serverbound"
+        );
+
+        self.insert::<P>(name, None);
+    }
+
+    fn insert<P: Packet>(&mut self, name: &'static str, decoder: Option<Decoder>) {
+        let key = Key {
+            version: self.version,
+            state: P::STATE,
+            direction: P::DIRECTION,
+            id: P::ID,
+        };
+
+        let previous = self.registry.entries.insert(key, Entry { name, decoder });
+        debug_assert!(previous.is_none(), "{key:?} is registered twice");
+    }
+}
+
+/// 1.20.1
+fn protocol_763(packets: &mut Registration<'_>) {
+    packets.serverbound("Handshake", |p| {
+        ServerBound::Handshake(handshaking::ServerBound::Handshake(p))
+    });
+
+    packets.serverbound("Status Request", |p| {
+        ServerBound::Status(status::ServerBound::StatusRequest(p))
+    });
+    packets.serverbound("Ping Request", |p| {
+        ServerBound::Status(status::ServerBound::PingRequest(p))
+    });
+    packets.clientbound::<status::StatusResponse>("Status Response");
+    packets.clientbound::<status::PingResponse>("Ping Response");
+
+    packets.serverbound("Login Start", |p| {
+        ServerBound::Login(login::ServerBound::LoginStart(p))
+    });
+    packets.serverbound("Encryption Response", |p| {
+        ServerBound::Login(login::ServerBound::EncryptionResponse(p))
+    });
+    packets.serverbound("Login Plugin Response", |p| {
+        ServerBound::Login(login::ServerBound::LoginPluginResponse(p))
+    });
+    packets.clientbound::<login::Disconnect>("Disconnect (login)");
+    packets.clientbound::<login::EncryptionRequest>("Encryption Request");
+    packets.clientbound::<login::LoginSuccess>("Login Success");
+    packets.clientbound::<login::SetCompression>("Set Compression");
+    packets.clientbound::<login::PluginRequest>("Login Plugin Request");
+
+    packets.serverbound("Confirm Teleportation", |p| {
+        ServerBound::Play(play::ServerBound::ConfirmTeleportation(p))
+    });
+    packets.serverbound("Client Information", |p| {
+        ServerBound::Play(play::ServerBound::ClientInformation(p))
+    });
+    packets.serverbound("Keep Alive", |p| {
+        ServerBound::Play(play::ServerBound::KeepAlive(p))
+    });
+    packets.clientbound::<play::Disconnect>("Disconnect (play)");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn play_packets() {
+        let registry = Registry::new();
+        let version = crate::PROTOCOL_VERSION;
+
+        let keep_alive = [0x12, 0, 0, 0, 0, 0, 0, 0x30, 0x39];
+        assert!(matches!(
+            registry.decode(version, State::Play, &keep_alive),
+            Ok(ServerBound::Play(play::ServerBound::KeepAlive(
+                play::KeepAlive {
+                    keep_alive_id: 12345
+                }
+            )))
+        ));
+
+        let confirm_teleportation = [0x00, 0x07];
+        assert!(matches!(
+            registry.decode(version, State::Play, &confirm_teleportation),
+            Ok(ServerBound::Play(play::ServerBound::ConfirmTeleportation(
+                play::ConfirmTeleportation {
+                    teleport_id: VarInt(7)
+                }
+            )))
+        ));
+
+        // what the client sends right after Login Success
+        let mut client_information = vec![0x08, 5];
+        client_information.extend_from_slice(b"en_us");
+        client_information.extend_from_slice(&[12, 0, 1, 0x7F, 1, 0, 1]);
+        let Ok(ServerBound::Play(play::ServerBound::ClientInformation(information))) =
+            registry.decode(version, State::Play, &client_information)
+        else {
+            panic!("Client Information didn't decode");
+        };
+        assert_eq!(information.locale.string, "en_us");
+        assert_eq!(information.view_distance, 12);
+        assert_eq!(information.displayed_skin_parts, 0x7F);
+        assert!(information.allow_server_listings);
+
+        assert_eq!(
+            registry.name(version, State::Play, Direction::ServerBound, 0x12),
+            Some("Keep Alive")
+        );
+    }
+
+    #[test]
+    fn unknown_ids() {
+        let registry = Registry::new();
+        let version = crate::PROTOCOL_VERSION;
+
+        assert!(matches!(
+            registry.decode(version, State::Play, &[0x7F]),
+            Err(ProtocolError::PacketId {
+                state: State::Play,
+                direction: Direction::ServerBound,
+                id: 0x7F,
+            })
+        ));
+
+        // known, but not in the state it came in
+        assert!(matches!(
+            registry.decode(version, State::Status, &[0x12]),
+            Err(ProtocolError::PacketId {
+                state: State::Status,
+                direction: Direction::ServerBound,
+                id: 0x12,
+            })
+        ));
+
+        // clientbound packets are never read, even with an id that's registered
+        assert!(matches!(
+            registry.decode(version, State::Login, &[0x03, 0x00]),
+            Err(ProtocolError::PacketId {
+                state: State::Login,
+                direction: Direction::ServerBound,
+                id: 0x03,
+            })
+        ));
+
+        assert!(registry.supports(version));
+        assert!(!registry.supports(version - 1));
+    }
+}
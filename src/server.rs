@@ -0,0 +1,304 @@
+//! State shared between every connection of a running server
+//!
+//! The accept loop in `main` owns the tasks, while the [`Server`] behind a
+//! [`ServerHandle`] keeps track of who is currently connected so any client
+//! task can ask about the others.
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Instant,
+};
+
+use rand::seq::SliceRandom;
+use tracing::warn;
+
+use crate::{
+    auth::{Authenticator, GameProfile, SessionServer},
+    chat::TextComponent,
+    config::ServerConfig,
+    crypto::ServerKeys,
+    favicon,
+    rate_limit::RateLimiter,
+    registry::Registry,
+    server_status::ServerStatus,
+    status::CachedStatus,
+    ProtocolError,
+};
+
+pub type ServerHandle = Arc<Server>;
+
+/// How many players the status response lists at most, same as vanilla
+const STATUS_SAMPLE_SIZE: usize = 12;
+
+#[derive(Debug, Clone)]
+pub struct ConnectedClient {
+    /// Where the client really is, which isn't the socket's peer when behind a proxy
+    pub addr: SocketAddr,
+    pub connected_at: Instant,
+    /// Who the client is, only known once they finished logging in
+    pub profile: Option<GameProfile>,
+}
+
+/// The last status packet, and how many times the status changed so far
+#[derive(Debug, Default)]
+struct StatusCache {
+    generation: u64,
+    response: Option<CachedStatus>,
+}
+
+#[derive(Debug)]
+pub struct Server {
+    /// Swapped out as a whole on reload, anyone still holding the old one keeps a consistent view
+    config: RwLock<Arc<ServerConfig>>,
+    keys: ServerKeys,
+    registry: Registry,
+    authenticator: Box<dyn Authenticator>,
+    /// Already encoded as a data URI, so a ping only has to copy it
+    favicon: RwLock<Option<String>>,
+    /// The encoded status packet, until something it shows changes
+    status_cache: Mutex<StatusCache>,
+    status_limiter: Option<RateLimiter>,
+    /// Keyed by the peer address of the socket, which stays the same for the whole connection
+    clients: RwLock<HashMap<SocketAddr, ConnectedClient>>,
+}
+
+impl Server {
+    pub fn new(config: ServerConfig) -> Result<Self, ProtocolError> {
+        let authenticator = Box::new(SessionServer::new(&config.session_server));
+
+        let favicon = favicon::load(&config.favicon_path);
+        let status_limiter = config.status_rate_limit.map(RateLimiter::new);
+
+        Ok(Self {
+            config: RwLock::new(Arc::new(config)),
+            keys: ServerKeys::generate()?,
+            registry: Registry::new(),
+            authenticator,
+            favicon: RwLock::new(favicon),
+            status_cache: Mutex::default(),
+            status_limiter,
+            clients: RwLock::default(),
+        })
+    }
+
+    pub fn config(&self) -> Arc<ServerConfig> {
+        Arc::clone(
+            &self
+                .config
+                .read()
+                .unwrap_or_else(std::sync::PoisonError::into_inner),
+        )
+    }
+
+    /// Start using `config`, for everything that can change without a restart
+    pub fn reload(&self, config: ServerConfig) {
+        let restart_required = self.config().restart_required(&config);
+        if !restart_required.is_empty() {
+            warn!(
+                "Changes to {} only take effect after a restart",
+                restart_required.join(", ")
+            );
+        }
+
+        *self
+            .config
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = Arc::new(config);
+
+        // the favicon path or the MOTD may have changed
+        self.reload_favicon();
+    }
+
+    pub const fn keys(&self) -> &ServerKeys {
+        &self.keys
+    }
+
+    pub const fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    pub fn authenticator(&self) -> &dyn Authenticator {
+        self.authenticator.as_ref()
+    }
+
+    /// Read the server icon again, in case it changed on disk
+    pub fn reload_favicon(&self) {
+        let favicon = favicon::load(&self.config().favicon_path);
+
+        *self
+            .favicon
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = favicon;
+        self.invalidate_status();
+    }
+
+    /// Add a freshly accepted connection to the registry
+    pub fn register(&self, addr: SocketAddr) {
+        self.clients_mut().insert(
+            addr,
+            ConnectedClient {
+                addr,
+                connected_at: Instant::now(),
+                profile: None,
+            },
+        );
+    }
+
+    /// Record where the client behind `peer` really is
+    pub fn set_address(&self, peer: &SocketAddr, addr: SocketAddr) {
+        if let Some(client) = self.clients_mut().get_mut(peer) {
+            client.addr = addr;
+        }
+    }
+
+    /// Record who the client behind `peer` logged in as, which makes them count as a player
+    pub fn set_profile(&self, peer: &SocketAddr, profile: GameProfile) {
+        if let Some(client) = self.clients_mut().get_mut(peer) {
+            client.profile = Some(profile);
+        }
+        self.invalidate_status();
+    }
+
+    /// Remove a connection from the registry, giving back what was known about it
+    pub fn unregister(&self, addr: &SocketAddr) -> Option<ConnectedClient> {
+        let client = self.clients_mut().remove(addr);
+
+        // only players show up in the status, everyone else can come and go
+        if client
+            .as_ref()
+            .is_some_and(|client| client.profile.is_some())
+        {
+            self.invalidate_status();
+        }
+
+        client
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients().len()
+    }
+
+    pub fn client_addrs(&self) -> Vec<SocketAddr> {
+        self.clients().keys().copied().collect()
+    }
+
+    /// Everyone who made it through login, as opposed to every open connection
+    pub fn players(&self) -> Vec<GameProfile> {
+        self.clients()
+            .values()
+            .filter_map(|client| client.profile.clone())
+            .collect()
+    }
+
+    /// What the server list shows about us right now
+    pub fn status(&self) -> ServerStatus {
+        let players = self.players();
+        // like vanilla, a random handful so big servers don't send their whole player list
+        let sample: Vec<GameProfile> = players
+            .choose_multiple(&mut rand::thread_rng(), STATUS_SAMPLE_SIZE)
+            .cloned()
+            .collect();
+
+        let config = self.config();
+
+        ServerStatus::new(
+            TextComponent::from_legacy(&config.motd),
+            config.max_players,
+            players.len(),
+            &sample,
+            self.favicon
+                .read()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .clone(),
+            config.enforce_secure_profile,
+        )
+    }
+
+    /// The status packet to send, only built again when the status changed since the last one
+    pub fn status_response(&self) -> Result<CachedStatus, ProtocolError> {
+        let generation = {
+            let cache = self.status_cache();
+            if let Some(response) = &cache.response {
+                return Ok(response.clone());
+            }
+            cache.generation
+        };
+
+        // two pings racing here both build it, which is cheaper than making every ping wait
+        let response = CachedStatus::new(&self.status())?;
+
+        // but if the status changed while building, this one might already be out of date
+        {
+            let mut cache = self.status_cache();
+            if cache.generation == generation {
+                cache.response = Some(response.clone());
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Whether `ip` may get the status right now, taking one of its tokens if so
+    pub fn allow_status(&self, ip: IpAddr) -> bool {
+        self.status_limiter
+            .as_ref()
+            .is_none_or(|limiter| limiter.try_acquire(ip))
+    }
+
+    fn invalidate_status(&self) {
+        let mut cache = self.status_cache();
+        cache.generation = cache.generation.wrapping_add(1);
+        cache.response = None;
+    }
+
+    fn status_cache(&self) -> MutexGuard<'_, StatusCache> {
+        self.status_cache
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    // a poisoned lock only means another client task panicked while holding it,
+    // the map itself is still usable so there's no reason to take everyone down
+    fn clients(&self) -> RwLockReadGuard<'_, HashMap<SocketAddr, ConnectedClient>> {
+        self.clients
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn clients_mut(&self) -> RwLockWriteGuard<'_, HashMap<SocketAddr, ConnectedClient>> {
+        self.clients
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_cache() {
+        let server = Server::new(ServerConfig::default()).unwrap();
+        let peer = SocketAddr::from(([127, 0, 0, 1], 50000));
+
+        server.status_response().unwrap();
+        assert!(server.status_cache().response.is_some());
+
+        // connecting isn't enough to show up, logging in is
+        server.register(peer);
+        assert!(server.status_cache().response.is_some());
+
+        server.set_profile(&peer, GameProfile::offline("jeb_".to_owned()));
+        let generation = server.status_cache().generation;
+        assert!(server.status_cache().response.is_none());
+        assert_eq!(server.players().len(), 1);
+
+        server.status_response().unwrap();
+        assert!(server.status_cache().response.is_some());
+
+        server.unregister(&peer);
+        assert!(server.status_cache().response.is_none());
+        assert_eq!(server.status_cache().generation, generation + 1);
+    }
+}
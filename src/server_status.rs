@@ -1,65 +1,85 @@
 use serde::Serialize;
 use uuid::Uuid;
 
-#[derive(Serialize)]
-pub struct ServerStatus<'a> {
-    version: ServerVersion<'a>,
-    players: ServerPlayers<'a>,
-    description: ServerDescription<'a>,
-    favicon: Option<&'a str>,
+use crate::{auth::GameProfile, chat::TextComponent};
+
+#[derive(Debug, Serialize)]
+pub struct ServerStatus {
+    version: ServerVersion,
+    players: ServerPlayers,
+    description: TextComponent,
+    favicon: Option<String>,
     #[serde(rename = "enforcesSecureChat")]
     enforces_secure_chat: bool,
     #[serde(rename = "previewsChat")]
     previews_chat: bool,
 }
 
-#[derive(Serialize)]
-struct ServerVersion<'a> {
-    name: &'a str,
-    protocol: usize,
+#[derive(Debug, Serialize)]
+struct ServerVersion {
+    name: String,
+    protocol: i32,
 }
 
-#[derive(Serialize)]
-struct ServerPlayers<'a> {
+#[derive(Debug, Serialize)]
+struct ServerPlayers {
     max: usize,
     online: usize,
-    sample: Vec<ServerPlayersSample<'a>>,
+    sample: Vec<ServerPlayersSample>,
 }
 
-#[derive(Serialize)]
-struct ServerPlayersSample<'a> {
-    name: &'a str,
-    #[serde(with = "uuid::serde::compact")]
+#[derive(Debug, Serialize)]
+struct ServerPlayersSample {
+    name: String,
     id: Uuid,
 }
 
-#[derive(Serialize)]
-struct ServerDescription<'a> {
-    text: &'a str,
-}
-
-impl ServerStatus<'_> {
-    pub fn get_example() -> Self {
-        ServerStatus {
+impl ServerStatus {
+    /// `sample` is only who shows up when hovering over the player count, `online` is the real count
+    pub fn new(
+        description: TextComponent,
+        max_players: usize,
+        online_players: usize,
+        sample: &[GameProfile],
+        favicon: Option<String>,
+        enforces_secure_chat: bool,
+    ) -> Self {
+        Self {
             version: ServerVersion {
-                name: "1.20.1",
-                protocol: 763,
+                name: crate::VERSION_NAME.to_owned(),
+                protocol: crate::PROTOCOL_VERSION,
             },
             players: ServerPlayers {
-                max: 100,
-                online: 105,
-                sample: vec![ServerPlayersSample {
-                    name: "thinkofdeath",
-                    // TODO dont do this too
-                    id: Uuid::parse_str("4566e69f-c907-48ee-8d71-d7ba5aa00d20").unwrap_or_default(),
-                }],
+                max: max_players,
+                online: online_players,
+                sample: sample
+                    .iter()
+                    .map(|profile| ServerPlayersSample {
+                        name: profile.name.clone(),
+                        id: profile.uuid,
+                    })
+                    .collect(),
             },
-            description: ServerDescription {
-                text: "Hello, world.",
-            },
-            favicon: None,
-            enforces_secure_chat: true,
-            previews_chat: true,
+            description,
+            favicon,
+            enforces_secure_chat,
+            previews_chat: false,
         }
     }
+
+    pub fn version_name(&self) -> &str {
+        &self.version.name
+    }
+
+    pub const fn online_players(&self) -> usize {
+        self.players.online
+    }
+
+    pub const fn max_players(&self) -> usize {
+        self.players.max
+    }
+
+    pub const fn description(&self) -> &TextComponent {
+        &self.description
+    }
 }